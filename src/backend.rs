@@ -1,208 +1,715 @@
+use crate::error::{Error, Result};
+use crate::layout::Constraint;
 use regex::Regex;
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::PathBuf;
-use std::process::Command;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct OutputMode {
     pub width: i32,
     pub height: i32,
-    pub refresh_rate: f32,
+    /// In Hz, `None` for virtual outputs that don't have one
+    pub refresh_rate: Option<f32>,
     pub current: bool,
     pub preferred: bool,
+    /// wlr-randr doesn't print this or `picture_aspect` so far. Both are
+    /// read in case it starts to, in the form guessed at by the tests.
+    pub interlaced: bool,
+    /// Picture aspect ratio from the EDID, like "16:9", when it's flagged
+    pub picture_aspect: Option<String>,
+}
+
+/// Names an output independently of where it sits in the list, which shifts
+/// whenever a monitor is plugged in or removed. Connector names stay put.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct OutputId(String);
+
+impl std::fmt::Display for OutputId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Output {
     pub name: String,
-    pub description: String,
-    pub make: String,
-    pub model: String,
-    pub serial: String,
-    pub physical_size: String,
+    pub description: Option<String>,
+    pub make: Option<String>,
+    pub model: Option<String>,
+    pub serial: Option<String>,
+    /// Width and height in millimetres, `None` when the EDID doesn't report it
+    pub physical_size: Option<(u32, u32)>,
     pub position: (i32, i32),
     pub scale: f32,
     pub transform: String,
     pub modes: Vec<OutputMode>,
     pub enabled: bool,
+    /// `None` when the compositor doesn't support adaptive sync
+    pub adaptive_sync: Option<bool>,
 }
 
-pub fn wlr_randr_get_outputs() -> Result<Vec<Output>, String> {
-    let output = Command::new("wlr-randr")
-        .output()
-        .map_err(|e| format!("Failed to run wlr-randr: {}", e))?;
+/// wlr-randr's transform names, in the order mangowc numbers them.
+pub const TRANSFORMS: [&str; 8] = [
+    "normal",
+    "90",
+    "180",
+    "270",
+    "flipped",
+    "flipped-90",
+    "flipped-180",
+    "flipped-270",
+];
+
+impl Output {
+    pub fn id(&self) -> OutputId {
+        OutputId(self.name.clone())
+    }
+
+    pub fn current_mode(&self) -> Option<&OutputMode> {
+        self.modes.iter().find(|m| m.current)
+    }
+
+    /// The mode to configure: the current one, or when the compositor didn't
+    /// say which is active, the preferred one and failing that the first.
+    pub fn effective_mode(&self) -> Option<&OutputMode> {
+        self.current_mode()
+            .or_else(|| self.modes.iter().find(|m| m.preferred))
+            .or_else(|| self.modes.first())
+    }
+
+    /// Marks the effective mode as current if nothing is, returning whether
+    /// anything had to change.
+    pub fn settle_mode(&mut self) -> bool {
+        if self.current_mode().is_some() {
+            return false;
+        }
+        let Some(mode) = self.effective_mode().cloned() else {
+            return false;
+        };
+        if let Some(m) = self.modes.iter_mut().find(|m| **m == mode) {
+            m.current = true;
+        }
+        true
+    }
+
+    /// Horizontal pixel density of the current mode, if the panel reports its size.
+    pub fn pixel_density(&self) -> Option<f32> {
+        let (width_mm, _) = self.physical_size?;
+        let mode = self.current_mode()?;
+        Some(crate::scale::pixel_density(mode.width, width_mm))
+    }
+
+    /// Every distinct resolution the output offers, largest first.
+    pub fn resolutions(&self) -> Vec<Resolution> {
+        let mut resolutions: Vec<Resolution> = Vec::new();
+        for m in &self.modes {
+            match resolutions
+                .iter_mut()
+                .find(|r| r.width == m.width && r.height == m.height)
+            {
+                Some(r) => r.preferred |= m.preferred,
+                None => resolutions.push(Resolution {
+                    width: m.width,
+                    height: m.height,
+                    preferred: m.preferred,
+                }),
+            }
+        }
+        resolutions.sort_by_key(|r| std::cmp::Reverse((r.width * r.height, r.width)));
+        resolutions
+    }
+
+    /// Indices into `modes` for each distinct refresh rate offered at a
+    /// resolution, fastest first.
+    pub fn refresh_rates(&self, width: i32, height: i32) -> Vec<usize> {
+        let mut rates: Vec<usize> = Vec::new();
+        for (i, m) in self.modes.iter().enumerate() {
+            let duplicate = rates
+                .iter()
+                .any(|j| self.modes[*j].refresh_rate == m.refresh_rate);
+            if m.width == width && m.height == height && !duplicate {
+                rates.push(i);
+            }
+        }
+        rates.sort_by(|a, b| {
+            let rate = |i: &usize| self.modes[*i].refresh_rate.unwrap_or(0.0);
+            rate(b).total_cmp(&rate(a))
+        });
+        rates
+    }
+}
+
+/// A distinct resolution, labelled for the mode picker.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Resolution {
+    pub width: i32,
+    pub height: i32,
+    pub preferred: bool,
+}
+
+impl std::fmt::Display for Resolution {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}x{} ({}",
+            self.width,
+            self.height,
+            aspect_ratio(self.width, self.height)
+        )?;
+        if self.preferred {
+            f.write_str(", preferred")?;
+        }
+        f.write_str(")")
+    }
+}
+
+// Panels are sold by these names even when the pixels don't reduce to them
+// exactly, e.g. 1366x768 or 3440x1440
+const ASPECT_NAMES: [(i32, i32); 8] = [
+    (16, 9),
+    (16, 10),
+    (4, 3),
+    (5, 4),
+    (3, 2),
+    (21, 9),
+    (32, 9),
+    (32, 10),
+];
+
+pub fn aspect_ratio(width: i32, height: i32) -> String {
+    if width <= 0 || height <= 0 {
+        return "?".to_string();
+    }
+    let ratio = width as f32 / height as f32;
+    let (w, h) = ASPECT_NAMES
+        .into_iter()
+        .min_by(|a, b| {
+            let da = (a.0 as f32 / a.1 as f32 - ratio).abs();
+            let db = (b.0 as f32 / b.1 as f32 - ratio).abs();
+            da.total_cmp(&db)
+        })
+        .filter(|(w, h)| (*w as f32 / *h as f32 - ratio).abs() / ratio < 0.03)
+        .unwrap_or_else(|| {
+            let (mut a, mut b) = (width, height);
+            while b != 0 {
+                (a, b) = (b, a % b);
+            }
+            (width / a, height / a)
+        });
+    format!("{}:{}", w, h)
+}
+
+// How long wlr-randr gets before it's assumed to be stuck
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Reads a child's pipe to the end on a thread of its own, so a chatty child
+/// can't fill the pipe and stall.
+fn drain(mut pipe: impl Read + Send + 'static) -> std::thread::JoinHandle<String> {
+    std::thread::spawn(move || {
+        let mut bytes = Vec::new();
+        let _ = pipe.read_to_end(&mut bytes);
+        String::from_utf8_lossy(&bytes).into_owned()
+    })
+}
+
+/// Runs `cmd` to completion and returns what it printed. It's killed if it
+/// outlives `COMMAND_TIMEOUT` or `cancel` gets set in the meantime.
+fn run_command(cmd: &mut Command, cancel: &AtomicBool) -> Result<String> {
+    let mut child = cmd
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => Error::WlrRandrMissing,
+            _ => Error::Spawn(e.to_string()),
+        })?;
+    let stdout = drain(child.stdout.take().expect("stdout is piped"));
+    let stderr = drain(child.stderr.take().expect("stderr is piped"));
+
+    let started = Instant::now();
+    let status = loop {
+        if let Some(status) = child
+            .try_wait()
+            .map_err(|e| Error::io("wait for", Path::new("wlr-randr"), e))?
+        {
+            break status;
+        }
+        let failure = if cancel.load(Ordering::Relaxed) {
+            Some(Error::Cancelled)
+        } else if started.elapsed() > COMMAND_TIMEOUT {
+            Some(Error::Timeout(COMMAND_TIMEOUT))
+        } else {
+            None
+        };
+        if let Some(failure) = failure {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(failure);
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    };
+
+    let stdout = stdout.join().unwrap_or_default();
+    let stderr = stderr.join().unwrap_or_default().trim().to_string();
+    if !status.success() {
+        // wlr-randr names the protocol it's missing on other compositors
+        if stderr.contains("output-management") || stderr.contains("output management") {
+            return Err(Error::NotWlroots(stderr));
+        }
+        return Err(Error::CommandFailed(stderr));
+    }
+    Ok(stdout)
+}
 
-    let stdout = String::from_utf8_lossy(&output.stdout);
+pub fn wlr_randr_get_outputs() -> Result<Vec<Output>> {
+    let stdout = run_command(&mut Command::new("wlr-randr"), &AtomicBool::new(false))?;
     parse_wlr_randr_output(&stdout)
 }
 
-pub fn parse_wlr_randr_output(output_str: &str) -> Result<Vec<Output>, String> {
-    let mut outputs = Vec::new();
-    let lines: Vec<&str> = output_str.lines().collect();
-
-    let mut current_output: Option<Output> = None;
-    let name_desc_regex = Regex::new(r#"^([^\s]+)\s+"(.*)""#).unwrap();
-    let pos_regex = Regex::new(r#"^  Position:\s+(-?\d+),(-?\d+)"#).unwrap();
-    let scale_regex = Regex::new(r#"^  Scale:\s+([0-9.]+)"#).unwrap();
-    let transform_regex = Regex::new(r#"^  Transform:\s+(.*)"#).unwrap();
-    let mode_regex = Regex::new(r#"^\s+(\d+)x(\d+) px, ([0-9.]+) Hz(?: \((.*)\))?"#).unwrap();
-    let make_regex = Regex::new(r#"^  Make:\s+(.*)"#).unwrap();
-    let model_regex = Regex::new(r#"^  Model:\s+(.*)"#).unwrap();
-    let serial_regex = Regex::new(r#"^  Serial:\s+(.*)"#).unwrap();
-    let phys_size_regex = Regex::new(r#"^  Physical size:\s+(.*)"#).unwrap();
-    let enabled_regex = Regex::new(r#"^  Enabled:\s+(yes|no)"#).unwrap();
+fn number<T: FromStr>(text: &str, fail: impl Fn(&str) -> Error) -> Result<T> {
+    T::from_str(text).map_err(|_| fail("expected a number in range"))
+}
+
+/// Reads the output of `wlr-randr`. Fields and mode flags it doesn't know yet
+/// are skipped so a newer wlr-randr still works, but a line that can't be
+/// made sense of is an error naming it. Disabled outputs don't report where they were, so they
+/// start out at the origin, unscaled and untransformed.
+pub fn parse_wlr_randr_output(output_str: &str) -> Result<Vec<Output>> {
+    let header_regex = Regex::new(r#"^(\S+)(?: "(.*)")?$"#).unwrap();
+    let field_regex = Regex::new(r#"^  ([A-Z][A-Za-z ]*):(?: (.*))?$"#).unwrap();
+    let mode_regex =
+        Regex::new(r#"^    (\d+)x(\d+) px(?:, ([0-9.]+) Hz)?(?: \((.*)\))?$"#).unwrap();
+    let size_regex = Regex::new(r#"^(\d+)x(\d+) mm$"#).unwrap();
+    let pos_regex = Regex::new(r#"^(-?\d+),(-?\d+)$"#).unwrap();
+    let aspect_regex = Regex::new(r#"^\d+:\d+$"#).unwrap();
 
+    let mut outputs: Vec<Output> = Vec::new();
+    // Where the current output starts, and which placement fields it gave
+    let mut header = (0, "");
+    let mut placed = [false; 3];
     let mut parsing_modes = false;
 
-    for line in lines {
+    let unplaced = |out: &Output, placed: [bool; 3], (line, text): (usize, &str)| {
+        (out.enabled && placed.contains(&false)).then(|| Error::Parse {
+            line,
+            text: text.to_string(),
+            reason: "enabled output without a position, scale or transform".to_string(),
+        })
+    };
+
+    for (n, line) in output_str.lines().enumerate() {
+        let fail = |reason: &str| Error::Parse {
+            line: n + 1,
+            text: line.to_string(),
+            reason: reason.to_string(),
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
         if !line.starts_with(' ') {
-            if let Some(out) = current_output.take() {
-                if out.modes.is_empty() {
-                    // huh
-                }
-                outputs.push(out);
+            if let Some(out) = outputs.last()
+                && let Some(e) = unplaced(out, placed, header)
+            {
+                return Err(e);
             }
-            if let Some(caps) = name_desc_regex.captures(line) {
-                current_output = Some(Output {
-                    name: caps.get(1).map_or("", |m| m.as_str()).to_string(),
-                    description: caps.get(2).map_or("", |m| m.as_str()).to_string(),
-                    make: String::new(),
-                    model: String::new(),
-                    serial: String::new(),
-                    physical_size: String::new(),
-                    position: (0, 0),
-                    scale: 1.0,
-                    transform: "normal".to_string(),
-                    modes: Vec::new(),
-                    enabled: true,
-                });
-                parsing_modes = false;
+            let caps = header_regex
+                .captures(line)
+                .ok_or_else(|| fail("expected an output name"))?;
+            outputs.push(Output {
+                name: caps[1].to_string(),
+                description: caps.get(2).map(|m| m.as_str().to_string()),
+                make: None,
+                model: None,
+                serial: None,
+                physical_size: None,
+                position: (0, 0),
+                scale: 1.0,
+                transform: "normal".to_string(),
+                modes: Vec::new(),
+                enabled: true,
+                adaptive_sync: None,
+            });
+            header = (n + 1, line);
+            placed = [false; 3];
+            parsing_modes = false;
+            continue;
+        }
+        let out = outputs
+            .last_mut()
+            .ok_or_else(|| fail("expected an output name before its details"))?;
+
+        if line.starts_with("    ") {
+            if !parsing_modes {
+                return Err(fail("mode listed outside the Modes section"));
             }
-        } else if let Some(out) = current_output.as_mut() {
-            if let Some(caps) = enabled_regex.captures(line) {
-                out.enabled = caps.get(1).unwrap().as_str() == "yes";
-            } else if let Some(caps) = make_regex.captures(line) {
-                out.make = caps.get(1).unwrap().as_str().to_string();
-            } else if let Some(caps) = model_regex.captures(line) {
-                out.model = caps.get(1).unwrap().as_str().to_string();
-            } else if let Some(caps) = serial_regex.captures(line) {
-                out.serial = caps.get(1).unwrap().as_str().to_string();
-            } else if let Some(caps) = phys_size_regex.captures(line) {
-                out.physical_size = caps.get(1).unwrap().as_str().to_string();
-            } else if let Some(caps) = pos_regex.captures(line) {
-                let x = i32::from_str(caps.get(1).unwrap().as_str()).unwrap_or(0);
-                let y = i32::from_str(caps.get(2).unwrap().as_str()).unwrap_or(0);
-                out.position = (x, y);
-            } else if let Some(caps) = scale_regex.captures(line) {
-                out.scale = f32::from_str(caps.get(1).unwrap().as_str()).unwrap_or(1.0);
-            } else if let Some(caps) = transform_regex.captures(line) {
-                out.transform = caps.get(1).unwrap().as_str().to_string();
-            } else if line.trim() == "Modes:" {
-                parsing_modes = true;
-            } else if parsing_modes {
-                if let Some(caps) = mode_regex.captures(line) {
-                    let w = i32::from_str(caps.get(1).unwrap().as_str()).unwrap_or(0);
-                    let h = i32::from_str(caps.get(2).unwrap().as_str()).unwrap_or(0);
-                    let freq = f32::from_str(caps.get(3).unwrap().as_str()).unwrap_or(0.0);
-
-                    let mut current = false;
-                    let mut preferred = false;
-
-                    if let Some(flags) = caps.get(4) {
-                        let f_str = flags.as_str();
-                        if f_str.contains("current") {
-                            current = true;
-                        }
-                        if f_str.contains("preferred") {
-                            preferred = true;
-                        }
+            let caps = mode_regex
+                .captures(line)
+                .ok_or_else(|| fail("expected a mode like '1920x1080 px, 60.000000 Hz'"))?;
+            let mut mode = OutputMode {
+                width: number(&caps[1], fail)?,
+                height: number(&caps[2], fail)?,
+                // Some virtual outputs print a rate of 0 rather than none
+                refresh_rate: caps
+                    .get(3)
+                    .map(|m| number(m.as_str(), fail))
+                    .transpose()?
+                    .filter(|rate: &f32| *rate > 0.0),
+                current: false,
+                preferred: false,
+                interlaced: false,
+                picture_aspect: None,
+            };
+            for flag in caps.get(4).map_or("", |m| m.as_str()).split(", ") {
+                match flag {
+                    "current" => mode.current = true,
+                    "preferred" => mode.preferred = true,
+                    "interlaced" => mode.interlaced = true,
+                    _ if aspect_regex.is_match(flag) => {
+                        mode.picture_aspect = Some(flag.to_string())
                     }
+                    _ => {}
+                }
+            }
+            out.modes.push(mode);
+            continue;
+        }
 
-                    out.modes.push(OutputMode {
-                        width: w,
-                        height: h,
-                        refresh_rate: freq,
-                        current,
-                        preferred,
-                    });
+        let caps = field_regex
+            .captures(line)
+            .ok_or_else(|| fail("expected a field like '  Enabled: yes'"))?;
+        let value = caps.get(2).map_or("", |m| m.as_str());
+        parsing_modes = false;
+        match &caps[1] {
+            "Make" => out.make = Some(value.to_string()),
+            "Model" => out.model = Some(value.to_string()),
+            "Serial" => out.serial = Some(value.to_string()),
+            "Physical size" => {
+                let size = size_regex
+                    .captures(value)
+                    .ok_or_else(|| fail("expected a size like '600x340 mm'"))?;
+                let w: u32 = number(&size[1], fail)?;
+                let h: u32 = number(&size[2], fail)?;
+                // Projectors and some virtual outputs report 0x0
+                out.physical_size = (w > 0 && h > 0).then_some((w, h));
+            }
+            "Enabled" => {
+                out.enabled = match value {
+                    "yes" => true,
+                    "no" => false,
+                    _ => return Err(fail("expected yes or no")),
                 }
             }
+            "Modes" => parsing_modes = true,
+            "Position" => {
+                let pos = pos_regex
+                    .captures(value)
+                    .ok_or_else(|| fail("expected a position like '1920,0'"))?;
+                out.position = (number(&pos[1], fail)?, number(&pos[2], fail)?);
+                placed[0] = true;
+            }
+            "Scale" => {
+                out.scale = number(value, fail)?;
+                if !out.scale.is_finite() || out.scale <= 0.0 {
+                    return Err(fail("scale must be positive"));
+                }
+                placed[1] = true;
+            }
+            "Transform" => {
+                if !TRANSFORMS.contains(&value) {
+                    return Err(fail("unknown transform"));
+                }
+                out.transform = value.to_string();
+                placed[2] = true;
+            }
+            "Adaptive Sync" => {
+                out.adaptive_sync = match value {
+                    "enabled" => Some(true),
+                    "disabled" => Some(false),
+                    _ => return Err(fail("expected enabled or disabled")),
+                }
+            }
+            _ => {}
         }
     }
-    if let Some(out) = current_output.take() {
-        outputs.push(out);
+    if let Some(out) = outputs.last()
+        && let Some(e) = unplaced(out, placed, header)
+    {
+        return Err(e);
     }
 
     Ok(outputs)
 }
 
-pub fn wlr_randr_apply(outputs: &[Output]) -> Result<(), String> {
-    let mut cmd = Command::new("wlr-randr");
+/// Folds a fresh read of the outputs into a layout that may have unsaved
+/// edits. `live` is what the previous read returned: an output that differs
+/// from it has been edited and keeps its edits, unless a different monitor
+/// now sits on that connector. Everything else, including outputs coming and
+/// going, follows `scanned`.
+pub fn merge_outputs(edited: &[Output], live: &[Output], scanned: &[Output]) -> Vec<Output> {
+    scanned
+        .iter()
+        .map(|new| {
+            let before = live.iter().find(|o| o.name == new.name);
+            let ours = edited.iter().find(|o| o.name == new.name);
+            match (before, ours) {
+                (Some(before), Some(ours))
+                    if ours != before
+                        && (&before.make, &before.model, &before.serial)
+                            == (&new.make, &new.model, &new.serial) =>
+                {
+                    ours.clone()
+                }
+                _ => new.clone(),
+            }
+        })
+        .collect()
+}
 
-    for out in outputs {
-        cmd.arg("--output").arg(&out.name);
-        if out.enabled {
-            cmd.arg("--on");
-            cmd.arg("--pos")
-                .arg(format!("{},{}", out.position.0, out.position.1));
-            cmd.arg("--scale").arg(format!("{:.6}", out.scale));
-            cmd.arg("--transform").arg(&out.transform);
-
-            if let Some(current_mode) = out.modes.iter().find(|m| m.current) {
-                cmd.arg("--mode").arg(format!(
-                    "{}x{}@{:.3}",
-                    current_mode.width, current_mode.height, current_mode.refresh_rate
-                ));
+fn mode_arg(mode: &OutputMode) -> String {
+    match mode.refresh_rate {
+        Some(rate) => format!("{}x{}@{:.3}", mode.width, mode.height, rate),
+        None => format!("{}x{}", mode.width, mode.height),
+    }
+}
+
+/// The wlr-randr arguments that take each output from `live` to `outputs`,
+/// leaving out settings that already match and outputs with nothing to
+/// change. Every change is a modeset, so anything skipped is flicker saved.
+pub fn apply_args(outputs: &[Output], live: &[Output]) -> Vec<(String, Vec<String>)> {
+    outputs
+        .iter()
+        .filter_map(|out| {
+            let have = live.iter().find(|o| o.name == out.name);
+            let mut args = Vec::new();
+            if !out.enabled {
+                if have.is_none_or(|h| h.enabled) {
+                    args.push("--off".to_string());
+                }
+                return (!args.is_empty()).then(|| (out.name.clone(), args));
             }
-        } else {
-            cmd.arg("--off");
+            // An output being switched on gets everything, since whatever it
+            // last had may no longer fit the layout
+            let have = have.filter(|h| h.enabled);
+            if have.is_none() {
+                args.push("--on".to_string());
+            }
+            let position = format!("{},{}", out.position.0, out.position.1);
+            if have.is_none_or(|h| h.position != out.position) {
+                args.extend(["--pos".to_string(), position]);
+            }
+            let scale = format!("{:.6}", out.scale);
+            if have.is_none_or(|h| format!("{:.6}", h.scale) != scale) {
+                args.extend(["--scale".to_string(), scale]);
+            }
+            if have.is_none_or(|h| h.transform != out.transform) {
+                args.extend(["--transform".to_string(), out.transform.clone()]);
+            }
+            if let Some(mode) = out.effective_mode().map(mode_arg)
+                && have.is_none_or(|h| h.current_mode().map(mode_arg) != Some(mode.clone()))
+            {
+                args.extend(["--mode".to_string(), mode]);
+            }
+            (!args.is_empty()).then(|| (out.name.clone(), args))
+        })
+        .collect()
+}
+
+fn run_apply(changes: &[(String, Vec<String>)], cancel: &AtomicBool) -> Result<()> {
+    let mut cmd = Command::new("wlr-randr");
+    for (name, args) in changes {
+        cmd.arg("--output").arg(name).args(args);
+    }
+    run_command(&mut cmd, cancel).map(|_| ())
+}
+
+/// Applies the layout live, touching only what differs from `live`, and
+/// returns the names of the outputs that changed. If wlr-randr rejects the
+/// whole lot, each output is retried alone to find the one at fault, and the
+/// ones that went through are put back so the layout isn't left half changed.
+/// Setting `cancel` stops a wlr-randr that is taking too long.
+pub fn wlr_randr_apply(
+    outputs: &[Output],
+    live: &[Output],
+    cancel: &AtomicBool,
+) -> Result<Vec<String>> {
+    let changes = apply_args(outputs, live);
+    let touched = changes.iter().map(|(name, _)| name.clone()).collect();
+    if changes.is_empty() {
+        return Ok(touched);
+    }
+    let combined = match run_apply(&changes, cancel) {
+        Ok(()) => return Ok(touched),
+        Err(e) => e,
+    };
+    if changes.len() < 2 || cancel.load(Ordering::Relaxed) {
+        return Err(combined);
+    }
+
+    let mut rejected = Vec::new();
+    let mut accepted = Vec::new();
+    for change in &changes {
+        if cancel.load(Ordering::Relaxed) {
+            // Cancelling takes back what was already applied too
+            roll_back(outputs, live, &accepted).ok();
+            return Err(Error::Cancelled);
         }
+        match run_apply(std::slice::from_ref(change), cancel) {
+            Ok(()) => accepted.push(change.0.clone()),
+            Err(e) => rejected.push((change.0.clone(), e)),
+        }
+    }
+    if rejected.is_empty() {
+        // Only the combination failed, one output at a time went through
+        return Ok(touched);
     }
+    let kept = match roll_back(outputs, live, &accepted) {
+        Ok(()) => Vec::new(),
+        Err(_) => accepted,
+    };
+    Err(Error::Rejected {
+        outputs: rejected,
+        kept,
+    })
+}
 
-    let status = cmd
-        .status()
-        .map_err(|e| format!("Failed to run wlr-randr: {}", e))?;
-    if !status.success() {
-        return Err("wlr-randr exited with non-zero status".to_string());
+/// Returns the `accepted` outputs to how `live` had them.
+fn roll_back(outputs: &[Output], live: &[Output], accepted: &[String]) -> Result<()> {
+    let pick = |list: &[Output]| -> Vec<Output> {
+        list.iter()
+            .filter(|o| accepted.contains(&o.name))
+            .cloned()
+            .collect()
+    };
+    let changes = apply_args(&pick(live), &pick(outputs));
+    if changes.is_empty() {
+        return Ok(());
     }
-    Ok(())
+    // Not cancellable, putting things back matters more than a slow compositor
+    run_apply(&changes, &AtomicBool::new(false))
 }
 
+/// One setting the compositor didn't take the way it was asked to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mismatch {
+    pub output: String,
+    pub setting: &'static str,
+    pub wanted: String,
+    pub got: String,
+}
+
+impl std::fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {} is {}, not {}",
+            self.output, self.setting, self.got, self.wanted
+        )
+    }
+}
+
+fn mode_label(mode: Option<&OutputMode>) -> String {
+    mode.map_or("none".to_string(), mode_arg)
+}
+
+/// Compares what was applied with what the compositor reports afterwards.
+/// Disabled outputs are only checked for being off.
+pub fn compare_applied(requested: &[Output], live: &[Output]) -> Vec<Mismatch> {
+    let mut mismatches = Vec::new();
+    for want in requested {
+        let mut differs = |setting, wanted: String, got: String| {
+            if wanted != got {
+                mismatches.push(Mismatch {
+                    output: want.name.clone(),
+                    setting,
+                    wanted,
+                    got,
+                });
+            }
+        };
+        let on_off = |enabled| if enabled { "on" } else { "off" }.to_string();
+        let Some(have) = live.iter().find(|o| o.name == want.name) else {
+            differs("state", on_off(want.enabled), "disconnected".to_string());
+            continue;
+        };
+        differs("state", on_off(want.enabled), on_off(have.enabled));
+        if !want.enabled || !have.enabled {
+            continue;
+        }
+        differs(
+            "mode",
+            mode_label(want.effective_mode()),
+            mode_label(have.current_mode()),
+        );
+        let position = |(x, y): (i32, i32)| format!("{},{}", x, y);
+        differs("position", position(want.position), position(have.position));
+        // wlr-randr prints six decimals, so only a real adjustment shows up here
+        differs(
+            "scale",
+            format!("{:.3}", want.scale),
+            format!("{:.3}", have.scale),
+        );
+        differs("transform", want.transform.clone(), have.transform.clone());
+    }
+    mismatches
+}
+
+// mangowc ignores comments, so relative placements ride along in monitors.conf
+const CONSTRAINT_PREFIX: &str = "# mdisplay-constraint:";
+
+/// The `monitorrule=` line mangowc needs to reproduce this output's setup.
+pub fn monitorrule_line(out: &Output) -> Result<String> {
+    let rr = TRANSFORMS
+        .iter()
+        .position(|t| *t == out.transform)
+        .unwrap_or(0);
+
+    // A 0x0 rule would leave mangowc guessing, so refuse rather than write one
+    let mode = out
+        .effective_mode()
+        .ok_or_else(|| Error::NoModes(out.name.clone()))?;
+
+    Ok(format!(
+        "monitorrule=name:{},width:{},height:{},refresh:{:.6},x:{},y:{},scale:{:.6},rr:{}",
+        out.name,
+        mode.width,
+        mode.height,
+        // mangowc picks the rate itself when given 0
+        mode.refresh_rate.unwrap_or(0.0),
+        out.position.0,
+        out.position.1,
+        out.scale,
+        rr
+    ))
+}
+
+/// Writes the layout to monitors.conf, snapshotting the user's own rules the
+/// first time. Returns problems that didn't stop the save, like a `source=`
+/// line pointing at a missing file.
 pub fn wlr_randr_save(
     outputs: &[Output],
+    constraints: &BTreeMap<String, Constraint>,
     settings: &crate::settings::AppSettings,
-) -> Result<(), String> {
+) -> Result<Vec<Error>> {
+    let mut warnings = Vec::new();
     let mut script = String::from("# Generated by mango-display\n\n");
 
+    for (name, constraint) in constraints {
+        script.push_str(&format!("{} {} {}\n", CONSTRAINT_PREFIX, name, constraint));
+    }
+    if !constraints.is_empty() {
+        script.push('\n');
+    }
+
     for out in outputs {
         if out.enabled {
-            let rr = match out.transform.as_str() {
-                "normal" => 0,
-                "90" => 1,
-                "180" => 2,
-                "270" => 3,
-                "flipped" => 4,
-                "flipped-90" => 5,
-                "flipped-180" => 6,
-                "flipped-270" => 7,
-                _ => 0,
-            };
-
-            let mut w = 0;
-            let mut h = 0;
-            let mut r = 0.0;
-
-            if let Some(current_mode) = out.modes.iter().find(|m| m.current) {
-                w = current_mode.width;
-                h = current_mode.height;
-                r = current_mode.refresh_rate;
-            }
-
-            script.push_str(&format!(
-                "monitorrule=name:{},width:{},height:{},refresh:{:.6},x:{},y:{},scale:{:.6},rr:{}\n",
-                out.name, w, h, r, out.position.0, out.position.1, out.scale, rr
-            ));
+            script.push_str(&monitorrule_line(out)?);
+            script.push('\n');
         } else {
             // This is just a placeholder as currently (according to the https://mangowc.vercel.app/docs/configuration/monitors)
             // there is no way to disable a monitor
@@ -210,16 +717,6 @@ pub fn wlr_randr_save(
         }
     }
 
-    let expand_path = |p: &str| -> PathBuf {
-        if p.starts_with("~/") {
-            dirs::home_dir()
-                .unwrap_or_else(|| PathBuf::from("/"))
-                .join(p.strip_prefix("~/").unwrap())
-        } else {
-            PathBuf::from(p)
-        }
-    };
-
     let monitors_path = expand_path(&settings.monitors_conf_path);
     let bak_path = expand_path(&settings.monitors_bak_path);
     let config_path = expand_path(&settings.config_conf_path);
@@ -254,10 +751,10 @@ pub fn wlr_randr_save(
 
         // Compact the path back to a tilde form for portability
         let to_portable = |p: &PathBuf| -> String {
-            if let Some(home) = dirs::home_dir() {
-                if let Ok(suffix) = p.strip_prefix(&home) {
-                    return format!("~/{}", suffix.display());
-                }
+            if let Some(home) = dirs::home_dir()
+                && let Ok(suffix) = p.strip_prefix(&home)
+            {
+                return format!("~/{}", suffix.display());
             }
             p.display().to_string()
         };
@@ -288,6 +785,12 @@ pub fn wlr_randr_save(
 
                     if let Some(raw) = sourced_path_str {
                         let sourced = resolve_source(raw);
+                        // There are no rules to keep from a file that isn't
+                        // there, but the user should know the line is dangling
+                        if !sourced.exists() {
+                            warnings.push(Error::IncludeNotFound(sourced));
+                            continue;
+                        }
                         let rules = collect_monitorrules(&sourced);
                         if !rules.is_empty() {
                             backup_entries.push(serde_json::json!({
@@ -302,15 +805,14 @@ pub fn wlr_randr_save(
 
         let backup_json = serde_json::json!({ "entries": backup_entries });
         fs::write(&bak_path, serde_json::to_string_pretty(&backup_json).unwrap_or_default())
-            .map_err(|e| format!("Failed to write monitors.bak: {}", e))?;
+            .map_err(|e| Error::io("write", &bak_path, e))?;
     }
 
     if let Some(parent) = monitors_path.parent() {
-        fs::create_dir_all(parent).map_err(|e| format!("Failed to create monitors dir: {}", e))?;
+        fs::create_dir_all(parent).map_err(|e| Error::io("create", parent, e))?;
     }
 
-    fs::write(&monitors_path, script)
-        .map_err(|e| format!("Failed to write monitors config: {}", e))?;
+    fs::write(&monitors_path, script).map_err(|e| Error::io("write", &monitors_path, e))?;
 
     if settings.auto_append_source {
         // Use the tilde path from settings (portable, good for dotfiles)
@@ -319,8 +821,8 @@ pub fn wlr_randr_save(
         let source_line_abs = format!("source={}", monitors_path.display());
         let source_line_abs_spaced = format!("source = {}", monitors_path.display());
         let needs_source = if config_path.exists() {
-            let content = fs::read_to_string(&config_path)
-                .map_err(|e| format!("Failed to read config file: {}", e))?;
+            let content =
+                fs::read_to_string(&config_path).map_err(|e| Error::io("read", &config_path, e))?;
             !content.contains(&source_line_tilde)
                 && !content.contains(&source_line_abs)
                 && !content.contains(&source_line_abs_spaced)
@@ -337,41 +839,70 @@ pub fn wlr_randr_save(
                 .create(true)
                 .append(true)
                 .open(&config_path)
-                .map_err(|e| format!("Failed to open config file: {}", e))?;
+                .map_err(|e| Error::io("open", &config_path, e))?;
 
             writeln!(file, "\n{}", source_line_tilde)
-                .map_err(|e| format!("Failed to write config file source override: {}", e))?;
+                .map_err(|e| Error::io("write", &config_path, e))?;
         }
     }
 
-    Ok(())
+    Ok(warnings)
 }
 
-pub fn wlr_randr_restore_default(settings: &crate::settings::AppSettings) -> Result<(), String> {
-    let expand_path = |p: &str| -> PathBuf {
-        if p.starts_with("~/") {
-            dirs::home_dir()
-                .unwrap_or_else(|| PathBuf::from("/"))
-                .join(p.strip_prefix("~/").unwrap())
-        } else {
-            PathBuf::from(p)
-        }
-    };
+/// Reads back the relative placements stored by `wlr_randr_save`, skipping
+/// any line that no longer parses.
+pub fn load_constraints(settings: &crate::settings::AppSettings) -> BTreeMap<String, Constraint> {
+    let monitors_path = expand_path(&settings.monitors_conf_path);
+    fs::read_to_string(monitors_path)
+        .map(|content| parse_constraints(&content))
+        .unwrap_or_default()
+}
+
+fn parse_constraints(content: &str) -> BTreeMap<String, Constraint> {
+    content
+        .lines()
+        .filter_map(|l| l.trim().strip_prefix(CONSTRAINT_PREFIX))
+        .filter_map(|rest| {
+            let (name, constraint) = rest.trim().split_once(' ')?;
+            Some((name.to_string(), constraint.parse().ok()?))
+        })
+        .collect()
+}
 
+fn expand_path(p: &str) -> PathBuf {
+    if p.starts_with("~/") {
+        dirs::home_dir()
+            .unwrap_or_else(|| PathBuf::from("/"))
+            .join(p.strip_prefix("~/").unwrap())
+    } else {
+        PathBuf::from(p)
+    }
+}
+
+pub fn wlr_randr_restore_default(settings: &crate::settings::AppSettings) -> Result<()> {
     let config_path = expand_path(&settings.config_conf_path);
     let monitors_path = expand_path(&settings.monitors_conf_path);
     let bak_path = expand_path(&settings.monitors_bak_path);
 
     // Parse the JSON backup
     let backup: serde_json::Value = if bak_path.exists() {
-        let raw = fs::read_to_string(&bak_path)
-            .map_err(|e| format!("Failed to read monitors.bak: {}", e))?;
-        serde_json::from_str(&raw).unwrap_or(serde_json::json!({ "entries": [] }))
+        let raw = fs::read_to_string(&bak_path).map_err(|e| Error::io("read", &bak_path, e))?;
+        // Restoring from a half-read backup would lose the rules it can't read
+        serde_json::from_str(&raw).map_err(|e| Error::BackupCorrupt {
+            path: bak_path.clone(),
+            reason: e.to_string(),
+        })?
     } else {
         serde_json::json!({ "entries": [] })
     };
 
-    let entries = backup["entries"].as_array().cloned().unwrap_or_default();
+    let entries = backup["entries"]
+        .as_array()
+        .cloned()
+        .ok_or_else(|| Error::BackupCorrupt {
+            path: bak_path.clone(),
+            reason: "it has no list of entries".to_string(),
+        })?;
 
     // Helper to strip all monitorrule lines from a file's content
     let strip_monitorrules = |content: &str| -> String {
@@ -391,8 +922,8 @@ pub fn wlr_randr_restore_default(settings: &crate::settings::AppSettings) -> Res
         let source_line_abs = format!("source={}", monitors_path.display());
         let source_line_abs_spaced = format!("source = {}", monitors_path.display());
 
-        let content = fs::read_to_string(&config_path)
-            .map_err(|e| format!("Failed to read config.conf: {}", e))?;
+        let content =
+            fs::read_to_string(&config_path).map_err(|e| Error::io("read", &config_path, e))?;
 
         let cleaned: String = content
             .lines()
@@ -407,8 +938,7 @@ pub fn wlr_randr_restore_default(settings: &crate::settings::AppSettings) -> Res
             .map(|l| format!("{}\n", l))
             .collect();
 
-        fs::write(&config_path, cleaned)
-            .map_err(|e| format!("Failed to write config.conf: {}", e))?;
+        fs::write(&config_path, cleaned).map_err(|e| Error::io("write", &config_path, e))?;
     }
 
     // Step 2: For each backup entry, restore rules into their original source file
@@ -433,14 +963,14 @@ pub fn wlr_randr_restore_default(settings: &crate::settings::AppSettings) -> Res
                 let cleaned = strip_monitorrules(&content);
                 let restored = format!("{}\n{}", cleaned.trim_end(), rules_block);
                 fs::write(&target_path, restored)
-                    .map_err(|e| format!("Failed to restore rules to {}: {}", source_file, e))?;
+                    .map_err(|e| Error::io("restore rules to", &target_path, e))?;
             } else {
                 // The file doesn't exist anymore, write it fresh
                 if let Some(parent) = target_path.parent() {
                     let _ = fs::create_dir_all(parent);
                 }
                 fs::write(&target_path, rules_block)
-                    .map_err(|e| format!("Failed to create {}: {}", source_file, e))?;
+                    .map_err(|e| Error::io("create", &target_path, e))?;
             }
         }
     }
@@ -453,8 +983,7 @@ pub fn wlr_randr_restore_default(settings: &crate::settings::AppSettings) -> Res
             .unwrap_or(false)
     });
     if monitors_path.exists() && !monitors_was_backed_up {
-        fs::remove_file(&monitors_path)
-            .map_err(|e| format!("Failed to delete monitors.conf: {}", e))?;
+        fs::remove_file(&monitors_path).map_err(|e| Error::io("delete", &monitors_path, e))?;
     }
 
     Ok(())
@@ -464,6 +993,11 @@ pub fn wlr_randr_restore_default(settings: &crate::settings::AppSettings) -> Res
 mod tests {
     use super::*;
 
+    fn laptop_and_monitor() -> Vec<Output> {
+        parse_wlr_randr_output(include_str!("../tests/fixtures/laptop_and_monitor.txt"))
+            .expect("Failed to parse")
+    }
+
     #[test]
     fn test_parse_wlr_randr() {
         let sample = r#"eDP-1 "Unknown Unknown Unknown"
@@ -495,28 +1029,285 @@ DP-1 "Acer Acer KG271 C 28243AAB48T0"
 
         let out1 = &outputs[0];
         assert_eq!(out1.name, "eDP-1");
-        assert_eq!(out1.enabled, true);
+        assert!(out1.enabled);
         assert_eq!(out1.position, (0, 0));
         assert_eq!(out1.scale, 1.0);
         assert_eq!(out1.transform, "normal");
         assert_eq!(out1.modes.len(), 1);
+        assert_eq!(out1.physical_size, Some((340, 190)));
         assert_eq!(out1.modes[0].width, 1920);
-        assert_eq!(out1.modes[0].refresh_rate, 60.0);
+        assert_eq!(out1.modes[0].refresh_rate, Some(60.0));
         assert!(out1.modes[0].current);
         assert!(out1.modes[0].preferred);
 
         let out2 = &outputs[1];
         assert_eq!(out2.name, "DP-1");
-        assert_eq!(out2.make, "Acer");
-        assert_eq!(out2.enabled, false);
+        assert_eq!(out2.make.as_deref(), Some("Acer"));
+        assert_eq!(out2.physical_size, Some((600, 340)));
+        assert!(!out2.enabled);
         assert_eq!(out2.position, (1920, 0));
         assert_eq!(out2.scale, 1.5);
         assert_eq!(out2.transform, "90");
         assert_eq!(out2.modes.len(), 2);
         assert_eq!(out2.modes[0].width, 1920);
-        assert_eq!(out2.modes[0].refresh_rate, 144.0);
+        assert_eq!(out2.modes[0].refresh_rate, Some(144.0));
         assert!(out2.modes[0].preferred);
         assert!(!out2.modes[0].current);
         assert!(!out2.modes[1].current);
+
+        // Nothing is current on DP-1, so its preferred mode stands in
+        assert_eq!(
+            out2.effective_mode().and_then(|m| m.refresh_rate),
+            Some(144.0)
+        );
+        assert!(
+            monitorrule_line(out2)
+                .unwrap()
+                .contains("width:1920,height:1080,refresh:144.000000")
+        );
+        let mut settled = out2.clone();
+        assert!(settled.settle_mode());
+        assert!(settled.modes[0].current);
+        assert!(!settled.settle_mode());
+
+        let no_modes = Output {
+            modes: Vec::new(),
+            ..out2.clone()
+        };
+        assert_eq!(
+            monitorrule_line(&no_modes),
+            Err(Error::NoModes("DP-1".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parse_fixtures() {
+        let docked = parse_wlr_randr_output(include_str!("../tests/fixtures/laptop_docked.txt"))
+            .expect("Failed to parse");
+        assert_eq!(docked.len(), 3);
+        assert_eq!(docked[0].serial, None);
+        assert_eq!(docked[0].adaptive_sync, Some(false));
+        assert_eq!(docked[1].position, (1600, -440));
+        assert_eq!(docked[1].adaptive_sync, Some(true));
+        // Disabled outputs report no placement and keep their modes
+        assert!(!docked[2].enabled);
+        assert_eq!(docked[2].position, (0, 0));
+        assert_eq!(docked[2].adaptive_sync, None);
+        assert_eq!(docked[2].modes.len(), 2);
+
+        let headless =
+            parse_wlr_randr_output(include_str!("../tests/fixtures/synthetic_headless.txt"))
+                .expect("Failed to parse");
+        assert_eq!(headless[0].description, None);
+        assert_eq!(headless[0].make, None);
+        assert_eq!(headless[0].modes[0].refresh_rate, None);
+        assert_eq!(
+            monitorrule_line(&headless[0]).unwrap(),
+            "monitorrule=name:HEADLESS-1,width:1920,height:1080,refresh:0.000000,x:0,y:0,scale:1.000000,rr:0"
+        );
+        assert_eq!(headless[1].physical_size, None);
+        assert_eq!(headless[1].modes[0].refresh_rate, None);
+        assert_eq!(headless[1].transform, "flipped-90");
+    }
+
+    #[test]
+    fn test_parse_unknown_flags_and_fields() {
+        // No wlr-randr prints these flags yet, so this only checks the
+        // parser's tolerance and the format it guesses at
+        let sample = "HDMI-A-1 \"TV\"
+  Enabled: yes
+  Modes:
+    3840x2160 px, 60.000000 Hz (preferred, current, 16:9)
+    1920x1080 px, 59.940000 Hz (interlaced, 16:9)
+    720x576 px, 50.000000 Hz (4:3, vrr)
+  Position: 0,0
+  Transform: normal
+  Scale: 2.000000
+  HDR: unsupported
+";
+        let tv = parse_wlr_randr_output(sample).expect("Failed to parse");
+        let modes = &tv[0].modes;
+        assert!(modes[0].current && modes[0].preferred);
+        assert_eq!(modes[0].picture_aspect.as_deref(), Some("16:9"));
+        assert!(modes[1].interlaced && !modes[0].interlaced);
+        assert_eq!(modes[2].picture_aspect.as_deref(), Some("4:3"));
+        assert_eq!(tv[0].scale, 2.0);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let line_of = |text: &str| match parse_wlr_randr_output(text) {
+            Err(Error::Parse { line, .. }) => Some(line),
+            _ => None,
+        };
+        let header = "DP-1 \"Monitor\"\n  Enabled: no\n";
+        assert_eq!(line_of("  Enabled: yes\n"), Some(1));
+        assert_eq!(line_of(&format!("{}  Enabled: maybe\n", header)), Some(3));
+        assert_eq!(line_of(&format!("{}    1920x1080 px\n", header)), Some(3));
+        assert_eq!(line_of(&format!("{}  Transform: 45\n", header)), Some(3));
+        assert_eq!(
+            line_of(&format!("{}  Position: 99999999999,0\n", header)),
+            Some(3)
+        );
+        assert_eq!(line_of(&format!("{}  Scale: 0.000000\n", header)), Some(3));
+        assert_eq!(line_of(&format!("{}garbled line\n", header)), Some(3));
+        // An enabled output has to say where it is
+        assert_eq!(
+            line_of("DP-1\n  Enabled: yes\nDP-2\n  Enabled: no\n"),
+            Some(1)
+        );
+        assert_eq!(line_of(&format!("{}  Future Field: 1\n", header)), None);
+    }
+
+    #[test]
+    fn test_resolutions_and_refresh_rates() {
+        let mode = |width, height, refresh_rate, preferred| OutputMode {
+            width,
+            height,
+            refresh_rate: Some(refresh_rate),
+            current: false,
+            preferred,
+            interlaced: false,
+            picture_aspect: None,
+        };
+        let out = Output {
+            name: "DP-1".to_string(),
+            description: None,
+            make: None,
+            model: None,
+            serial: None,
+            physical_size: None,
+            position: (0, 0),
+            scale: 1.0,
+            transform: "normal".to_string(),
+            modes: vec![
+                mode(1920, 1080, 60.0, false),
+                mode(2560, 1440, 60.0, false),
+                mode(2560, 1440, 143.973, true),
+                mode(1280, 1024, 60.0, false),
+                mode(2560, 1440, 60.0, false),
+            ],
+            enabled: true,
+            adaptive_sync: None,
+        };
+        let resolutions = out.resolutions();
+        assert_eq!(resolutions.len(), 3);
+        assert_eq!(resolutions[0].to_string(), "2560x1440 (16:9, preferred)");
+        assert_eq!(resolutions[2].to_string(), "1280x1024 (5:4)");
+        assert_eq!(out.refresh_rates(2560, 1440), vec![2, 1]);
+        assert_eq!(aspect_ratio(1366, 768), "16:9");
+        assert_eq!(aspect_ratio(3440, 1440), "21:9");
+        assert_eq!(aspect_ratio(1000, 100), "10:1");
+    }
+
+    #[test]
+    fn test_merge_outputs() {
+        let live = laptop_and_monitor();
+        let mut edited = live.clone();
+        edited[1].position = (0, 1080);
+
+        // Unplugging the laptop panel drops it but keeps the moved monitor
+        let scanned = vec![live[1].clone()];
+        let merged = merge_outputs(&edited, &live, &scanned);
+        assert_eq!(merged.len(), 1);
+        assert_eq!(merged[0].position, (0, 1080));
+
+        // Untouched outputs pick up outside changes, and new ones appear
+        let mut scanned = live.clone();
+        scanned[0].scale = 2.0;
+        scanned.push(Output {
+            name: "HDMI-A-1".to_string(),
+            ..live[1].clone()
+        });
+        let merged = merge_outputs(&edited, &live, &scanned);
+        assert_eq!(merged.len(), 3);
+        assert_eq!(merged[0].scale, 2.0);
+        assert_eq!(merged[1].position, (0, 1080));
+
+        // A different monitor on the same connector starts afresh
+        scanned[1].serial = Some("OTHER".to_string());
+        let merged = merge_outputs(&edited, &live, &scanned);
+        assert_eq!(merged[1].position, (1920, 0));
+    }
+
+    #[test]
+    fn test_apply_args() {
+        let live = laptop_and_monitor();
+        assert!(apply_args(&live, &live).is_empty());
+
+        let mut wanted = live.clone();
+        wanted[1].position = (1920, 200);
+        wanted[1].modes[0].current = false;
+        wanted[1].modes[1].current = true;
+        let changes = apply_args(&wanted, &live);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].0, "DP-1");
+        assert_eq!(
+            changes[0].1,
+            vec!["--pos", "1920,200", "--mode", "1920x1080@60.000"]
+        );
+
+        // Switching off sends nothing else, switching on sends everything
+        wanted[0].enabled = false;
+        assert_eq!(apply_args(&wanted, &live)[0].1, vec!["--off"]);
+        let mut off = live.clone();
+        off[0].enabled = false;
+        let changes = apply_args(&live, &off);
+        assert_eq!(changes[0].1[0], "--on");
+        assert_eq!(changes[0].1.len(), 9);
+    }
+
+    #[test]
+    fn test_compare_applied() {
+        // Just the monitor
+        let live = laptop_and_monitor()[1..].to_vec();
+        assert!(compare_applied(&live, &live).is_empty());
+
+        let mut requested = live.clone();
+        requested[0].modes[0].current = false;
+        requested[0].modes[1].current = true;
+        requested[0].scale = 1.25;
+        let mismatches = compare_applied(&requested, &live);
+        assert_eq!(mismatches.len(), 2);
+        assert_eq!(
+            mismatches[0].to_string(),
+            "DP-1: mode is 1920x1080@144.000, not 1920x1080@60.000"
+        );
+        assert_eq!(mismatches[1].setting, "scale");
+
+        // Only being off matters for a disabled output
+        requested[0].enabled = false;
+        let mut off = live.clone();
+        off[0].enabled = false;
+        off[0].scale = 2.0;
+        assert!(compare_applied(&requested, &off).is_empty());
+        assert_eq!(
+            compare_applied(&requested, &[])[0].to_string(),
+            "DP-1: state is disconnected, not off"
+        );
+    }
+
+    #[test]
+    fn test_run_command() {
+        let idle = AtomicBool::new(false);
+        let printed = run_command(Command::new("echo").arg("hello"), &idle);
+        assert_eq!(printed, Ok("hello\n".to_string()));
+        assert!(run_command(&mut Command::new("false"), &idle).is_err());
+
+        let started = Instant::now();
+        let cancelled = run_command(Command::new("sleep").arg("5"), &AtomicBool::new(true));
+        assert_eq!(cancelled, Err(Error::Cancelled));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_parse_constraints() {
+        let conf = "# Generated by mango-display\n\n\
+            # mdisplay-constraint: DP-1 right-of eDP-1 centre\n\
+            # mdisplay-constraint: DP-2 sideways DP-1 start\n\n\
+            monitorrule=name:DP-1,width:1920,height:1080,refresh:60.000000,x:1920,y:60,scale:1.000000,rr:0\n";
+        let constraints = parse_constraints(conf);
+        assert_eq!(constraints.len(), 1);
+        assert_eq!(constraints["DP-1"].to_string(), "right-of eDP-1 centre");
     }
 }
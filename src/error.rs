@@ -0,0 +1,220 @@
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Everything that can go wrong talking to wlr-randr or touching the config
+/// files, split by what the user can do about it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// wlr-randr isn't installed, or isn't on PATH
+    WlrRandrMissing,
+    /// The compositor doesn't offer wlr-output-management, so it isn't
+    /// wlroots-based. Carries what wlr-randr said about it.
+    NotWlroots(String),
+    /// A line of wlr-randr output that couldn't be understood, counted from 1
+    Parse {
+        line: usize,
+        text: String,
+        reason: String,
+    },
+    PermissionDenied(PathBuf),
+    /// monitors.bak exists but isn't the snapshot MDisplay wrote
+    BackupCorrupt {
+        path: PathBuf,
+        reason: String,
+    },
+    /// A `source=` line points at a file that isn't there
+    IncludeNotFound(PathBuf),
+    /// Any other file operation that failed, `action` being e.g. "write"
+    Io {
+        action: &'static str,
+        path: PathBuf,
+        reason: String,
+    },
+    /// Settings that couldn't be turned into JSON
+    Serialize(String),
+    /// wlr-randr is there but couldn't be started, e.g. it isn't executable
+    Spawn(String),
+    /// wlr-randr ran but failed, with whatever it printed on stderr
+    CommandFailed(String),
+    Timeout(Duration),
+    Cancelled,
+    /// The background thread doing the work died before reporting back
+    WorkerLost,
+    /// Outputs wlr-randr refused when they were applied one at a time. The
+    /// ones it took are put back, `kept` naming any that couldn't be.
+    Rejected {
+        outputs: Vec<(String, Error)>,
+        kept: Vec<String>,
+    },
+    /// The output reports no modes, so there is nothing to write a rule for
+    NoModes(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// Wraps a failed file operation, singling out permission problems.
+    pub fn io(action: &'static str, path: &Path, e: io::Error) -> Self {
+        if e.kind() == io::ErrorKind::PermissionDenied {
+            return Error::PermissionDenied(path.to_path_buf());
+        }
+        Error::Io {
+            action,
+            path: path.to_path_buf(),
+            reason: e.to_string(),
+        }
+    }
+
+    /// What the user can do about it, when there's something to suggest.
+    pub fn guidance(&self) -> Option<&'static str> {
+        match self {
+            Error::WlrRandrMissing => Some(
+                "Install wlr-randr from your distribution's packages and make sure it is on PATH.",
+            ),
+            Error::NotWlroots(_) => Some(
+                "MDisplay needs a wlroots-based compositor such as mangowc that supports wlr-output-management.",
+            ),
+            Error::Parse { .. } => Some(
+                "Your wlr-randr may be newer than MDisplay understands. Please report the line above.",
+            ),
+            Error::PermissionDenied(_) => Some(
+                "Check who owns the file, or choose other paths with --set-monitors-path and --set-config-path.",
+            ),
+            Error::BackupCorrupt { .. } => Some(
+                "Fix or delete the backup. Without one, the next save snapshots your current monitor rules afresh.",
+            ),
+            Error::IncludeNotFound(_) => {
+                Some("Fix or remove the source= line in your config.conf.")
+            }
+            Error::Spawn(_) => Some("Check that wlr-randr is executable by your user."),
+            Error::Timeout(_) => Some("The compositor may be stuck. Try again in a moment."),
+            Error::Rejected { .. } => {
+                Some("Check that the rejected outputs support the chosen mode, scale and position.")
+            }
+            Error::NoModes(_) => {
+                Some("Disable the output, or reconnect it so it reports its modes.")
+            }
+            Error::Io { .. }
+            | Error::Serialize(_)
+            | Error::CommandFailed(_)
+            | Error::Cancelled
+            | Error::WorkerLost => None,
+        }
+    }
+
+    /// Process exit code for the command line, following sysexits.h.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::WlrRandrMissing | Error::NotWlroots(_) => 69,
+            Error::Parse { .. } | Error::BackupCorrupt { .. } | Error::NoModes(_) => 65,
+            Error::IncludeNotFound(_) => 66,
+            Error::Serialize(_)
+            | Error::CommandFailed(_)
+            | Error::Rejected { .. }
+            | Error::WorkerLost => 70,
+            Error::Spawn(_) => 71,
+            Error::Io { .. } => 74,
+            Error::Timeout(_) => 75,
+            Error::PermissionDenied(_) => 77,
+            Error::Cancelled => 130,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::WlrRandrMissing => f.write_str("wlr-randr is not installed"),
+            Error::NotWlroots(detail) => {
+                write!(
+                    f,
+                    "The compositor doesn't support output management ({})",
+                    detail
+                )
+            }
+            Error::Parse { line, text, reason } => {
+                write!(
+                    f,
+                    "Couldn't read wlr-randr line {}, {}: '{}'",
+                    line, reason, text
+                )
+            }
+            Error::PermissionDenied(path) => write!(f, "Permission denied for {}", path.display()),
+            Error::BackupCorrupt { path, reason } => {
+                write!(f, "The backup at {} is corrupt: {}", path.display(), reason)
+            }
+            Error::IncludeNotFound(path) => {
+                write!(f, "The sourced file {} doesn't exist", path.display())
+            }
+            Error::Io {
+                action,
+                path,
+                reason,
+            } => write!(f, "Failed to {} {}: {}", action, path.display(), reason),
+            Error::Serialize(reason) => write!(f, "Failed to serialize the settings: {}", reason),
+            Error::Spawn(reason) => write!(f, "Couldn't start wlr-randr: {}", reason),
+            Error::CommandFailed(stderr) if stderr.is_empty() => {
+                f.write_str("wlr-randr exited with non-zero status")
+            }
+            Error::CommandFailed(stderr) => write!(f, "wlr-randr failed: {}", stderr),
+            Error::Timeout(after) => {
+                write!(
+                    f,
+                    "wlr-randr didn't finish within {} seconds",
+                    after.as_secs()
+                )
+            }
+            Error::Cancelled => f.write_str("Cancelled"),
+            Error::WorkerLost => f.write_str("The background task stopped without finishing"),
+            Error::Rejected { outputs, kept } => {
+                f.write_str("wlr-randr rejected ")?;
+                for (n, (name, e)) in outputs.iter().enumerate() {
+                    if n > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{} ({})", name, e)?;
+                }
+                if !kept.is_empty() {
+                    write!(
+                        f,
+                        ", and {} couldn't be put back, so the layout is only partly applied",
+                        kept.join(", ")
+                    )?;
+                }
+                Ok(())
+            }
+            Error::NoModes(name) => {
+                write!(f, "{} reports no modes, so there is nothing to save", name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_io_errors() {
+        let path = Path::new("/etc/mango/monitors.conf");
+        let denied = Error::io(
+            "write",
+            path,
+            io::Error::from(io::ErrorKind::PermissionDenied),
+        );
+        assert_eq!(denied, Error::PermissionDenied(path.to_path_buf()));
+        assert_eq!(denied.exit_code(), 77);
+        assert!(denied.guidance().is_some());
+
+        let full = Error::io("write", path, io::Error::other("disk full"));
+        assert_eq!(
+            full.to_string(),
+            "Failed to write /etc/mango/monitors.conf: disk full"
+        );
+        assert_eq!(full.guidance(), None);
+    }
+}
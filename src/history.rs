@@ -0,0 +1,248 @@
+use std::time::{Duration, Instant};
+
+// Oldest entries are dropped past this many undo steps
+pub const LIMIT: usize = 100;
+
+// Coalescing edits further apart than this start a new undo entry
+pub const COALESCE_WINDOW: Duration = Duration::from_secs(1);
+
+/// A state as it was right before an edit, labelled with that edit.
+struct Entry<T> {
+    label: String,
+    state: T,
+}
+
+/// Undo and redo stacks of whole states.
+pub struct History<T> {
+    undo: Vec<Entry<T>>,
+    redo: Vec<Entry<T>>,
+    /// Label of the gesture whose edits are being coalesced, and when the
+    /// last of them came in
+    coalescing: Option<(String, Instant)>,
+}
+
+impl<T> Default for History<T> {
+    fn default() -> Self {
+        Self {
+            undo: Vec::new(),
+            redo: Vec::new(),
+            coalescing: None,
+        }
+    }
+}
+
+impl<T> History<T> {
+    /// Records `current` before an edit. Consecutive coalescing edits with
+    /// the same label, like the stream of moves during a drag or typing into
+    /// a field, collapse into the first one's entry as long as they follow
+    /// each other within COALESCE_WINDOW.
+    pub fn checkpoint(&mut self, label: String, coalesce: bool, current: impl FnOnce() -> T) {
+        self.checkpoint_at(label, coalesce, current, Instant::now());
+    }
+
+    fn checkpoint_at(
+        &mut self,
+        label: String,
+        coalesce: bool,
+        current: impl FnOnce() -> T,
+        now: Instant,
+    ) {
+        if coalesce
+            && let Some((gesture, last)) = &mut self.coalescing
+            && *gesture == label
+            && now.saturating_duration_since(*last) < COALESCE_WINDOW
+        {
+            *last = now;
+            return;
+        }
+        self.undo.push(Entry {
+            label: label.clone(),
+            state: current(),
+        });
+        if self.undo.len() > LIMIT {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+        self.coalescing = coalesce.then_some((label, now));
+    }
+
+    /// A coalescing checkpoint for a drag, which stays one gesture however
+    /// long the pointer rests, until `end_gesture` ends it.
+    pub fn drag_checkpoint(&mut self, label: String, current: impl FnOnce() -> T) {
+        if self
+            .coalescing
+            .as_ref()
+            .is_some_and(|(gesture, _)| *gesture == label)
+        {
+            return;
+        }
+        self.checkpoint(label, true, current);
+    }
+
+    /// Stops coalescing, so the next edit gets an entry of its own.
+    pub fn end_gesture(&mut self) {
+        self.coalescing = None;
+    }
+
+    /// Ends the gesture being coalesced and returns its label.
+    pub fn take_gesture(&mut self) -> Option<String> {
+        self.coalescing.take().map(|(label, _)| label)
+    }
+
+    /// Renames the latest entry, returning false if there is none.
+    pub fn relabel(&mut self, label: &str) -> bool {
+        match self.undo.last_mut() {
+            Some(top) => {
+                top.label = label.to_string();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Swaps `current` for the state before the latest edit.
+    pub fn undo(&mut self, current: &mut T) -> bool {
+        let Some(entry) = self.undo.pop() else {
+            return false;
+        };
+        self.redo.push(Entry {
+            label: entry.label,
+            state: std::mem::replace(current, entry.state),
+        });
+        true
+    }
+
+    /// Swaps `current` for the state after the latest undone edit.
+    pub fn redo(&mut self, current: &mut T) -> bool {
+        let Some(entry) = self.redo.pop() else {
+            return false;
+        };
+        self.undo.push(Entry {
+            label: entry.label,
+            state: std::mem::replace(current, entry.state),
+        });
+        true
+    }
+
+    /// Undoes edits until only the first `len` are left.
+    pub fn jump(&mut self, len: usize, current: &mut T) {
+        while self.undo.len() > len && self.undo(current) {}
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Labels of the edits that can be undone, oldest first.
+    pub fn undo_labels(&self) -> impl Iterator<Item = &str> {
+        self.undo.iter().map(|e| e.label.as_str())
+    }
+
+    /// Labels of the edits that can be redone, in the order redo replays them.
+    pub fn redo_labels(&self) -> impl Iterator<Item = &str> {
+        self.redo.iter().rev().map(|e| e.label.as_str())
+    }
+
+    /// Every stored state, for fixing them all up at once.
+    pub fn states_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.undo
+            .iter_mut()
+            .chain(&mut self.redo)
+            .map(|e| &mut e.state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(history: &History<i32>) -> Vec<&str> {
+        history.undo_labels().collect()
+    }
+
+    #[test]
+    fn test_checkpoint_coalesces_within_window() {
+        let mut history = History::default();
+        let start = Instant::now();
+        let half = COALESCE_WINDOW / 2;
+
+        history.checkpoint_at("Move A".to_string(), true, || 0, start);
+        history.checkpoint_at("Move A".to_string(), true, || 1, start + half);
+        // Each edit restarts the window, so a steady stream stays one entry
+        history.checkpoint_at("Move A".to_string(), true, || 2, start + half * 2);
+        assert_eq!(labels(&history), ["Move A"]);
+
+        history.checkpoint_at("Move A".to_string(), true, || 3, start + half * 6);
+        history.checkpoint_at("Move B".to_string(), true, || 4, start + half * 6);
+        history.checkpoint_at("Move B".to_string(), false, || 5, start + half * 6);
+        assert_eq!(labels(&history), ["Move A", "Move A", "Move B", "Move B"]);
+
+        let mut current = 6;
+        history.jump(0, &mut current);
+        assert_eq!(current, 0);
+    }
+
+    #[test]
+    fn test_drag_checkpoint_coalesces_until_finished() {
+        let mut history = History::default();
+        history.drag_checkpoint("Move A".to_string(), || 0);
+        // A drag that rests past the window is still the same gesture
+        history.coalescing.as_mut().unwrap().1 -= COALESCE_WINDOW * 2;
+        history.drag_checkpoint("Move A".to_string(), || 1);
+        assert_eq!(labels(&history), ["Move A"]);
+
+        history.end_gesture();
+        history.drag_checkpoint("Move A".to_string(), || 2);
+        assert_eq!(labels(&history), ["Move A", "Move A"]);
+
+        assert_eq!(history.take_gesture().as_deref(), Some("Move A"));
+        assert!(history.relabel("Disable A"));
+        assert_eq!(labels(&history), ["Move A", "Disable A"]);
+    }
+
+    #[test]
+    fn test_history_limit() {
+        let mut history = History::default();
+        for i in 0..LIMIT as i32 + 5 {
+            history.checkpoint(i.to_string(), false, || i);
+        }
+        let kept = labels(&history);
+        assert_eq!(kept.len(), LIMIT);
+        assert_eq!(kept[0], "5");
+    }
+
+    #[test]
+    fn test_undo_redo_and_jump() {
+        let mut history = History::default();
+        let mut current = 0;
+        for i in 1..=3 {
+            history.checkpoint(format!("Set {}", i), false, || current);
+            current = i;
+        }
+
+        assert!(history.undo(&mut current));
+        assert_eq!(current, 2);
+        assert!(history.redo(&mut current));
+        assert_eq!(current, 3);
+        assert!(!history.redo(&mut current));
+
+        history.jump(1, &mut current);
+        assert_eq!(current, 1);
+        assert_eq!(labels(&history), ["Set 1"]);
+        assert_eq!(
+            history.redo_labels().collect::<Vec<_>>(),
+            ["Set 2", "Set 3"]
+        );
+
+        // A new edit forgets what was undone
+        history.checkpoint("Set 4".to_string(), false, || current);
+        assert!(!history.can_redo());
+        history.jump(0, &mut current);
+        assert_eq!(current, 0);
+        assert!(!history.can_undo());
+    }
+}
@@ -0,0 +1,1003 @@
+use crate::backend::{Output, OutputMode};
+use crate::scale;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+// Gaps up to this many logical pixels are treated as a slip of the mouse
+// rather than a deliberate separation
+pub const GAP_TOLERANCE: i32 = 10;
+
+// Logical size assumed for an output that reports no usable mode, so it
+// still has somewhere to be drawn and placed
+pub const FALLBACK_SIZE: (i32, i32) = (1920, 1080);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub w: i32,
+    pub h: i32,
+}
+
+impl Rect {
+    pub fn right(&self) -> i32 {
+        self.x + self.w
+    }
+
+    pub fn bottom(&self) -> i32 {
+        self.y + self.h
+    }
+
+    /// Length of the shared span on the x axis, negative when apart.
+    fn overlap_x(&self, other: &Rect) -> i32 {
+        self.right().min(other.right()) - self.x.max(other.x)
+    }
+
+    /// Length of the shared span on the y axis, negative when apart.
+    fn overlap_y(&self, other: &Rect) -> i32 {
+        self.bottom().min(other.bottom()) - self.y.max(other.y)
+    }
+
+    pub fn intersects(&self, other: &Rect) -> bool {
+        self.overlap_x(other) > 0 && self.overlap_y(other) > 0
+    }
+
+    /// Distance between facing edges of two rectangles that share a span on
+    /// the other axis, or `None` if they don't face each other at all.
+    pub fn edge_gap(&self, other: &Rect) -> Option<i32> {
+        if self.overlap_y(other) > 0 {
+            Some(-self.overlap_x(other))
+        } else if self.overlap_x(other) > 0 {
+            Some(-self.overlap_y(other))
+        } else {
+            None
+        }
+    }
+}
+
+pub fn logical_size(out: &Output, cm: &OutputMode) -> (i32, i32) {
+    let w = (cm.width as f32 / out.scale) as i32;
+    let h = (cm.height as f32 / out.scale) as i32;
+    match out.transform.as_str() {
+        "90" | "270" | "flipped-90" | "flipped-270" => (h, w),
+        _ => (w, h),
+    }
+}
+
+pub fn logical_rect(out: &Output) -> Rect {
+    let (w, h) = match out.effective_mode() {
+        Some(cm) => logical_size(out, cm),
+        None => FALLBACK_SIZE,
+    };
+    Rect {
+        x: out.position.0,
+        y: out.position.1,
+        w,
+        h,
+    }
+}
+
+/// Real-world width and height in millimetres as the panel is mounted, with
+/// its transform applied.
+pub fn physical_extent(out: &Output) -> Option<(f32, f32)> {
+    let (w, h) = out.physical_size?;
+    let (turns, _) = parse_transform(&out.transform);
+    let (w, h) = (w as f32, h as f32);
+    Some(if turns % 2 == 1 { (h, w) } else { (w, h) })
+}
+
+/// Logical pixels per millimetre, assuming the reference density when the
+/// panel doesn't report its size.
+pub fn px_per_mm(out: &Output) -> f32 {
+    match physical_extent(out) {
+        Some((w, _)) if w > 0.0 => logical_rect(out).w as f32 / w,
+        _ => scale::REFERENCE_PPI / 25.4,
+    }
+}
+
+const ROTATIONS: [&str; 4] = ["normal", "90", "180", "270"];
+
+/// Splits a wlr-randr transform into clockwise quarter turns of the panel
+/// and whether it is flipped.
+pub fn parse_transform(transform: &str) -> (i32, bool) {
+    let (flipped, base) = match transform.strip_prefix("flipped") {
+        Some(rest) => (true, rest.trim_start_matches('-')),
+        None => (false, transform),
+    };
+    let base = if base.is_empty() { "normal" } else { base };
+    let turns = ROTATIONS.iter().position(|t| *t == base).unwrap_or(0) as i32;
+    (turns, flipped)
+}
+
+fn format_transform(quarter_turns: i32, flipped: bool) -> String {
+    match (flipped, ROTATIONS[quarter_turns.rem_euclid(4) as usize]) {
+        (false, t) => t.to_string(),
+        (true, "normal") => "flipped".to_string(),
+        (true, t) => format!("flipped-{}", t),
+    }
+}
+
+/// Turns a transform by quarter turns, keeping any flip.
+pub fn rotate_transform(transform: &str, quarter_turns: i32) -> String {
+    let (current, flipped) = parse_transform(transform);
+    format_transform(current + quarter_turns, flipped)
+}
+
+/// Mirrors a transform left to right, keeping its rotation.
+pub fn flip_transform(transform: &str) -> String {
+    let (current, flipped) = parse_transform(transform);
+    format_transform(current, !flipped)
+}
+
+/// A straight measurement between two facing edges, in logical pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Measure {
+    pub from: (i32, i32),
+    pub to: (i32, i32),
+    pub distance: i32,
+}
+
+/// Distance from each side of `moving` to the closest rectangle it faces on
+/// that side, measured across the middle of the span they share.
+pub fn measure_neighbours(moving: Rect, others: &[Rect]) -> Vec<Measure> {
+    let mut nearest: [Option<Measure>; 4] = [None; 4];
+    for o in others {
+        let candidates = if moving.overlap_y(o) > 0 {
+            let y = (moving.y.max(o.y) + moving.bottom().min(o.bottom())) / 2;
+            [
+                (0, (moving.right(), y), (o.x, y), o.x - moving.right()),
+                (1, (moving.x, y), (o.right(), y), moving.x - o.right()),
+            ]
+        } else if moving.overlap_x(o) > 0 {
+            let x = (moving.x.max(o.x) + moving.right().min(o.right())) / 2;
+            [
+                (2, (x, moving.bottom()), (x, o.y), o.y - moving.bottom()),
+                (3, (x, moving.y), (x, o.bottom()), moving.y - o.bottom()),
+            ]
+        } else {
+            continue;
+        };
+        for (side, from, to, distance) in candidates {
+            if distance < 0 || nearest[side].is_some_and(|m| m.distance <= distance) {
+                continue;
+            }
+            nearest[side] = Some(Measure { from, to, distance });
+        }
+    }
+    nearest.into_iter().flatten().collect()
+}
+
+/// Finds the nearest position along one axis, strictly in the direction of
+/// `sign`, where an edge of `moving` lines up with an edge of another rectangle.
+pub fn next_edge(moving: Rect, others: &[Rect], horizontal: bool, sign: i32) -> Option<i32> {
+    let (start, size) = if horizontal {
+        (moving.x, moving.w)
+    } else {
+        (moving.y, moving.h)
+    };
+    others
+        .iter()
+        .flat_map(|o| {
+            let (near, far) = if horizontal {
+                (o.x, o.right())
+            } else {
+                (o.y, o.bottom())
+            };
+            [near, far, near - size, far - size]
+        })
+        .filter(|c| (c - start).signum() == sign.signum())
+        .min_by_key(|c| (c - start).abs())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SnapConfig {
+    /// How close, in logical pixels, an edge has to be before it snaps
+    pub threshold: i32,
+    /// Grid used when no edge is in reach, 0 or less turns it off
+    pub grid: i32,
+    /// Also line up centres, not just edges
+    pub centre_lines: bool,
+}
+
+impl Default for SnapConfig {
+    fn default() -> Self {
+        Self {
+            threshold: 40,
+            grid: 10,
+            centre_lines: true,
+        }
+    }
+}
+
+/// A line another output's edge or centre sits on, in logical coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Guide {
+    Vertical(i32),
+    Horizontal(i32),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapped {
+    pub x: i32,
+    pub y: i32,
+    /// Every line the snapped rectangle now shares with a neighbour
+    pub guides: Vec<Guide>,
+}
+
+// Edge lines of a rectangle along one axis: start, end and centre
+fn lines(start: i32, len: i32) -> [i32; 3] {
+    [start, start + len, start + len / 2]
+}
+
+fn snap_axis(
+    start: i32,
+    len: i32,
+    candidates: &[[i32; 3]],
+    config: &SnapConfig,
+) -> (i32, Vec<i32>) {
+    let mine = lines(start, len);
+    let used = if config.centre_lines { 3 } else { 2 };
+
+    let mut best: Option<(i32, i32)> = None;
+    for theirs in candidates {
+        for (k, my_line) in mine.iter().enumerate().take(used) {
+            for their_line in theirs.iter().take(used) {
+                let dist = (my_line - their_line).abs();
+                if dist < config.threshold && best.is_none_or(|(d, _)| dist < d) {
+                    // Shift the whole rectangle so this pair of lines coincides
+                    let offset = [0, len, len / 2][k];
+                    best = Some((dist, their_line - offset));
+                }
+            }
+        }
+    }
+
+    let snapped = match best {
+        Some((_, pos)) => pos,
+        None if config.grid > 0 => (start as f32 / config.grid as f32).round() as i32 * config.grid,
+        None => start,
+    };
+
+    let mine = lines(snapped, len);
+    let mut guides: Vec<i32> = candidates
+        .iter()
+        .flat_map(|theirs| theirs.iter().take(used).copied())
+        .filter(|l| mine[..used].contains(l))
+        .collect();
+    guides.sort_unstable();
+    guides.dedup();
+    (snapped, guides)
+}
+
+/// Snaps a rectangle being dragged against its neighbours on both axes at
+/// once. Only neighbours within reach on the other axis are considered, so
+/// an output doesn't jump to line up with one on the far side of the layout.
+pub fn snap(moving: Rect, others: &[Rect], config: &SnapConfig) -> Snapped {
+    let t = config.threshold;
+    let near_x: Vec<[i32; 3]> = others
+        .iter()
+        .filter(|o| moving.y < o.bottom() + t && moving.bottom() > o.y - t)
+        .map(|o| lines(o.x, o.w))
+        .collect();
+    let near_y: Vec<[i32; 3]> = others
+        .iter()
+        .filter(|o| moving.x < o.right() + t && moving.right() > o.x - t)
+        .map(|o| lines(o.y, o.h))
+        .collect();
+
+    let (x, vertical) = snap_axis(moving.x, moving.w, &near_x, config);
+    let (y, horizontal) = snap_axis(moving.y, moving.h, &near_y, config);
+    Snapped {
+        x,
+        y,
+        guides: vertical
+            .into_iter()
+            .map(Guide::Vertical)
+            .chain(horizontal.into_iter().map(Guide::Horizontal))
+            .collect(),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    RightOf,
+    LeftOf,
+    Above,
+    Below,
+}
+
+impl Side {
+    pub const ALL: [Side; 4] = [Side::RightOf, Side::LeftOf, Side::Above, Side::Below];
+
+    pub fn is_horizontal(&self) -> bool {
+        matches!(self, Side::RightOf | Side::LeftOf)
+    }
+
+    fn keyword(&self) -> &'static str {
+        match self {
+            Side::RightOf => "right-of",
+            Side::LeftOf => "left-of",
+            Side::Above => "above",
+            Side::Below => "below",
+        }
+    }
+}
+
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Side::RightOf => "Right of",
+            Side::LeftOf => "Left of",
+            Side::Above => "Above",
+            Side::Below => "Below",
+        })
+    }
+}
+
+/// Where along the anchor's edge an output sits: top/left, centre or bottom/right.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Start,
+    Centre,
+    End,
+}
+
+impl Align {
+    pub const ALL: [Align; 3] = [Align::Start, Align::Centre, Align::End];
+
+    pub fn label(&self, side: Side) -> &'static str {
+        match (self, side.is_horizontal()) {
+            (Align::Start, true) => "Top aligned",
+            (Align::Start, false) => "Left aligned",
+            (Align::Centre, _) => "Centred",
+            (Align::End, true) => "Bottom aligned",
+            (Align::End, false) => "Right aligned",
+        }
+    }
+
+    fn keyword(&self) -> &'static str {
+        match self {
+            Align::Start => "start",
+            Align::Centre => "centre",
+            Align::End => "end",
+        }
+    }
+}
+
+/// Keeps an output attached to one side of another whatever their sizes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Constraint {
+    pub anchor: String,
+    pub side: Side,
+    pub align: Align,
+}
+
+/// Serialised as `right-of DP-1 start`
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {}",
+            self.side.keyword(),
+            self.anchor,
+            self.align.keyword()
+        )
+    }
+}
+
+impl FromStr for Constraint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split_whitespace().collect();
+        let [side, anchor, align] = parts[..] else {
+            return Err(format!("Expected '<side> <output> <align>', got '{}'", s));
+        };
+        let side = Side::ALL
+            .into_iter()
+            .find(|x| x.keyword() == side)
+            .ok_or_else(|| format!("Unknown side '{}'", side))?;
+        let align = Align::ALL
+            .into_iter()
+            .find(|x| x.keyword() == align)
+            .ok_or_else(|| format!("Unknown alignment '{}'", align))?;
+        Ok(Constraint {
+            anchor: anchor.to_string(),
+            side,
+            align,
+        })
+    }
+}
+
+impl Constraint {
+    /// Top-left corner for a rectangle of `size` placed against `anchor`.
+    pub fn place(&self, size: (i32, i32), anchor: Rect) -> (i32, i32) {
+        let (w, h) = size;
+        let along = |start: i32, anchor_len: i32, len: i32| match self.align {
+            Align::Start => start,
+            Align::Centre => start + (anchor_len - len) / 2,
+            Align::End => start + anchor_len - len,
+        };
+        match self.side {
+            Side::RightOf => (anchor.right(), along(anchor.y, anchor.h, h)),
+            Side::LeftOf => (anchor.x - w, along(anchor.y, anchor.h, h)),
+            Side::Above => (along(anchor.x, anchor.w, w), anchor.y - h),
+            Side::Below => (along(anchor.x, anchor.w, w), anchor.bottom()),
+        }
+    }
+}
+
+/// True if placing `name` ends up following `target` through a chain of anchors.
+pub fn depends_on(constraints: &BTreeMap<String, Constraint>, name: &str, target: &str) -> bool {
+    let mut current = name;
+    // A chain can't be longer than the number of constraints without looping
+    for _ in 0..=constraints.len() {
+        if current == target {
+            return true;
+        }
+        match constraints.get(current) {
+            Some(c) => current = &c.anchor,
+            None => return false,
+        }
+    }
+    false
+}
+
+/// Moves every constrained rectangle next to its anchor. Anchors are resolved
+/// before the outputs that follow them; cycles and missing anchors are skipped.
+pub fn solve(rects: &mut [(String, Rect)], constraints: &BTreeMap<String, Constraint>) {
+    for _ in 0..rects.len() {
+        let mut changed = false;
+        for i in 0..rects.len() {
+            let Some(c) = constraints.get(&rects[i].0) else {
+                continue;
+            };
+            let Some(anchor) = rects.iter().find(|(n, _)| *n == c.anchor).map(|(_, r)| *r) else {
+                continue;
+            };
+            if c.anchor == rects[i].0 {
+                continue;
+            }
+            let rect = &mut rects[i].1;
+            let (x, y) = c.place((rect.w, rect.h), anchor);
+            if (x, y) != (rect.x, rect.y) {
+                rect.x = x;
+                rect.y = y;
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Issue {
+    Overlap(usize, usize),
+    Gap(usize, usize, i32),
+    /// Outputs the cursor can't reach from the rest of the layout
+    Island(Vec<usize>),
+}
+
+impl Issue {
+    pub fn outputs(&self) -> Vec<usize> {
+        match self {
+            Issue::Overlap(a, b) | Issue::Gap(a, b, _) => vec![*a, *b],
+            Issue::Island(members) => members.clone(),
+        }
+    }
+
+    /// Overlaps make the compositor render one output on top of another,
+    /// everything else is merely awkward to use
+    pub fn is_blocking(&self) -> bool {
+        matches!(self, Issue::Overlap(..))
+    }
+}
+
+/// Checks positioned rectangles, each tagged with the index of its output.
+/// `seam` gives the gap in logical pixels deliberately left between two
+/// outputs, like the one bezel compensation opens, which isn't a problem.
+pub fn validate(rects: &[(usize, Rect)], seam: impl Fn(usize, usize) -> i32) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    let mut group: Vec<usize> = (0..rects.len()).collect();
+
+    fn root(group: &mut [usize], mut i: usize) -> usize {
+        while group[i] != i {
+            group[i] = group[group[i]];
+            i = group[i];
+        }
+        i
+    }
+
+    for i in 0..rects.len() {
+        for j in (i + 1)..rects.len() {
+            let (a, ra) = rects[i];
+            let (b, rb) = rects[j];
+            // Outputs covering exactly the same area mirror each other
+            let connected = if ra == rb {
+                true
+            } else if ra.intersects(&rb) {
+                issues.push(Issue::Overlap(a, b));
+                true
+            } else {
+                match ra.edge_gap(&rb) {
+                    Some(0) => true,
+                    Some(gap) if seam(a, b) > 0 && gap <= seam(a, b) + GAP_TOLERANCE => true,
+                    Some(gap) if gap <= GAP_TOLERANCE => {
+                        issues.push(Issue::Gap(a, b, gap));
+                        true
+                    }
+                    _ => false,
+                }
+            };
+            if connected {
+                let (ri, rj) = (root(&mut group, i), root(&mut group, j));
+                group[ri] = rj;
+            }
+        }
+    }
+
+    let mut islands: Vec<Vec<usize>> = Vec::new();
+    let mut roots: Vec<usize> = Vec::new();
+    for (i, (idx, _)) in rects.iter().enumerate() {
+        let r = root(&mut group, i);
+        match roots.iter().position(|x| *x == r) {
+            Some(k) => islands[k].push(*idx),
+            None => {
+                roots.push(r);
+                islands.push(vec![*idx]);
+            }
+        }
+    }
+    if islands.len() > 1 {
+        // The biggest group is the layout proper, the rest are stranded from it
+        let main = islands
+            .iter()
+            .enumerate()
+            .max_by_key(|(k, island)| (island.len(), std::cmp::Reverse(*k)))
+            .map(|(k, _)| k)
+            .unwrap_or(0);
+        for (k, island) in islands.into_iter().enumerate() {
+            if k != main {
+                issues.push(Issue::Island(island));
+            }
+        }
+    }
+
+    issues
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arrangement {
+    LeftToRight,
+    TopToBottom,
+    AlignTop,
+    AlignMiddle,
+    AlignBottom,
+    /// Slide everything left and up until it touches a neighbour, keeping order
+    Pack,
+}
+
+impl Arrangement {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Arrangement::LeftToRight => "Left to Right",
+            Arrangement::TopToBottom => "Top to Bottom",
+            Arrangement::AlignTop => "Align Tops",
+            Arrangement::AlignMiddle => "Align Centres",
+            Arrangement::AlignBottom => "Align Bottoms",
+            Arrangement::Pack => "Pack",
+        }
+    }
+}
+
+/// Computes new top-left corners for `rects`, which are expected in tab order.
+pub fn arrange(rects: &[Rect], arrangement: Arrangement) -> Vec<(i32, i32)> {
+    match arrangement {
+        Arrangement::LeftToRight => {
+            let mut x = 0;
+            rects
+                .iter()
+                .map(|r| {
+                    let pos = (x, 0);
+                    x += r.w;
+                    pos
+                })
+                .collect()
+        }
+        Arrangement::TopToBottom => {
+            let mut y = 0;
+            rects
+                .iter()
+                .map(|r| {
+                    let pos = (0, y);
+                    y += r.h;
+                    pos
+                })
+                .collect()
+        }
+        Arrangement::AlignTop => {
+            let top = rects.iter().map(|r| r.y).min().unwrap_or(0);
+            rects.iter().map(|r| (r.x, top)).collect()
+        }
+        Arrangement::AlignBottom => {
+            let bottom = rects.iter().map(Rect::bottom).max().unwrap_or(0);
+            rects.iter().map(|r| (r.x, bottom - r.h)).collect()
+        }
+        Arrangement::AlignMiddle => {
+            // Centre on the tallest output so it stays put
+            let middle = rects
+                .iter()
+                .max_by_key(|r| r.h)
+                .map(|r| r.y + r.h / 2)
+                .unwrap_or(0);
+            rects.iter().map(|r| (r.x, middle - r.h / 2)).collect()
+        }
+        Arrangement::Pack => {
+            let mut packed = rects.to_vec();
+            pack_axis(&mut packed, true);
+            pack_axis(&mut packed, false);
+            packed.iter().map(|r| (r.x, r.y)).collect()
+        }
+    }
+}
+
+/// Opens up the seams between outputs that face each other so each pair ends
+/// up at least `gap(a, b)` apart, pushing whatever lies beyond along with it.
+/// Space that is already there counts, so running it twice changes nothing.
+pub fn spread(rects: &[Rect], gap: impl Fn(usize, usize) -> i32) -> Vec<(i32, i32)> {
+    let mut spread = rects.to_vec();
+    spread_axis(&mut spread, true, &gap);
+    spread_axis(&mut spread, false, &gap);
+    spread.iter().map(|r| (r.x, r.y)).collect()
+}
+
+fn spread_axis(rects: &mut [Rect], horizontal: bool, gap: &impl Fn(usize, usize) -> i32) {
+    let original = rects.to_vec();
+    let start = |r: &Rect| if horizontal { r.x } else { r.y };
+    let end = |r: &Rect| if horizontal { r.right() } else { r.bottom() };
+    let mut order: Vec<usize> = (0..rects.len()).collect();
+    order.sort_by_key(|i| start(&rects[*i]));
+
+    for (n, &i) in order.iter().enumerate() {
+        let pushed = order[..n]
+            .iter()
+            .filter(|&&j| {
+                let facing = if horizontal {
+                    original[i].overlap_y(&original[j]) > 0
+                } else {
+                    original[i].overlap_x(&original[j]) > 0
+                };
+                facing && end(&original[j]) <= start(&original[i]) + GAP_TOLERANCE
+            })
+            .map(|&j| {
+                let existing = start(&original[i]) - end(&original[j]);
+                end(&rects[j]) + existing.max(gap(j, i))
+            })
+            .max();
+        if let Some(pushed) = pushed {
+            if horizontal {
+                rects[i].x = pushed;
+            } else {
+                rects[i].y = pushed;
+            }
+        }
+    }
+}
+
+/// Lays the outputs out at their real size, `sizes` being each one's width
+/// and height in millimetres. Panels keep the order and the gaps they have in
+/// the logical layout, gaps scaled by `mm_per_px`, so ones that touch there
+/// still touch whatever their density. One with nothing before it on an axis
+/// lines up with a panel it shares that axis with, like one stacked above.
+pub fn physical_positions(rects: &[Rect], sizes: &[(f32, f32)], mm_per_px: f32) -> Vec<(f32, f32)> {
+    let xs = physical_axis(rects, sizes, mm_per_px, true);
+    let ys = physical_axis(rects, sizes, mm_per_px, false);
+    xs.into_iter().zip(ys).collect()
+}
+
+fn physical_axis(
+    rects: &[Rect],
+    sizes: &[(f32, f32)],
+    mm_per_px: f32,
+    horizontal: bool,
+) -> Vec<f32> {
+    let start = |r: &Rect| if horizontal { r.x } else { r.y };
+    let end = |r: &Rect| if horizontal { r.right() } else { r.bottom() };
+    let size = |i: usize| if horizontal { sizes[i].0 } else { sizes[i].1 };
+    let mut order: Vec<usize> = (0..rects.len()).collect();
+    order.sort_by_key(|i| start(&rects[*i]));
+
+    let mut placed = vec![0.0; rects.len()];
+    for (n, &i) in order.iter().enumerate() {
+        placed[i] = order[..n]
+            .iter()
+            .filter(|&&j| {
+                let facing = if horizontal {
+                    rects[i].overlap_y(&rects[j]) > 0
+                } else {
+                    rects[i].overlap_x(&rects[j]) > 0
+                };
+                facing && end(&rects[j]) <= start(&rects[i]) + GAP_TOLERANCE
+            })
+            .map(|&j| placed[j] + size(j) + (start(&rects[i]) - end(&rects[j])) as f32 * mm_per_px)
+            .reduce(f32::max)
+            .unwrap_or_else(|| {
+                let along = |j: usize| {
+                    if horizontal {
+                        rects[i].overlap_x(&rects[j])
+                    } else {
+                        rects[i].overlap_y(&rects[j])
+                    }
+                };
+                match order[..n]
+                    .iter()
+                    .copied()
+                    .filter(|&j| along(j) > 0)
+                    .max_by_key(|&j| along(j))
+                {
+                    Some(j) => placed[j] + (start(&rects[i]) - start(&rects[j])) as f32 * mm_per_px,
+                    None => start(&rects[i]) as f32 * mm_per_px,
+                }
+            });
+    }
+    placed
+}
+
+// Walks the rectangles in order along one axis and moves each back to the far
+// edge of whatever precedes it on that axis, or to zero if nothing does
+fn pack_axis(rects: &mut [Rect], horizontal: bool) {
+    let mut order: Vec<usize> = (0..rects.len()).collect();
+    order.sort_by_key(|i| if horizontal { rects[*i].x } else { rects[*i].y });
+
+    for (n, &i) in order.iter().enumerate() {
+        let start = order[..n]
+            .iter()
+            .map(|&j| rects[j])
+            .filter(|other| {
+                if horizontal {
+                    rects[i].overlap_y(other) > 0
+                } else {
+                    rects[i].overlap_x(other) > 0
+                }
+            })
+            .map(|other| {
+                if horizontal {
+                    other.right()
+                } else {
+                    other.bottom()
+                }
+            })
+            .max()
+            .unwrap_or(0);
+        if horizontal {
+            rects[i].x = start;
+        } else {
+            rects[i].y = start;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: i32, y: i32, w: i32, h: i32) -> Rect {
+        Rect { x, y, w, h }
+    }
+
+    #[test]
+    fn test_validate_clean_layout() {
+        let rects = [
+            (0, rect(0, 0, 1920, 1080)),
+            (1, rect(1920, 0, 2560, 1440)),
+            (2, rect(0, 1080, 1920, 1080)),
+        ];
+        assert!(validate(&rects, |_, _| 0).is_empty());
+
+        let mirrored = [(0, rect(0, 0, 1920, 1080)), (1, rect(0, 0, 1920, 1080))];
+        assert!(validate(&mirrored, |_, _| 0).is_empty());
+    }
+
+    #[test]
+    fn test_validate_reports_problems() {
+        let rects = [
+            (0, rect(0, 0, 1920, 1080)),
+            (1, rect(1900, 0, 1920, 1080)),
+            (2, rect(0, 1084, 1800, 1080)),
+            (3, rect(8000, 0, 1920, 1080)),
+        ];
+        let issues = validate(&rects, |_, _| 0);
+        assert!(issues.contains(&Issue::Overlap(0, 1)));
+        assert!(issues.contains(&Issue::Gap(0, 2, 4)));
+        assert!(issues.contains(&Issue::Island(vec![3])));
+        assert_eq!(issues.len(), 3);
+
+        // A seam opened on purpose for bezels is neither a gap nor an island
+        let seamed = [(0, rect(0, 0, 1920, 1080)), (1, rect(1960, 0, 1920, 1080))];
+        assert_eq!(validate(&seamed, |_, _| 0), vec![Issue::Island(vec![1])]);
+        assert!(validate(&seamed, |_, _| 40).is_empty());
+    }
+
+    #[test]
+    fn test_rotate_and_next_edge() {
+        assert_eq!(rotate_transform("normal", 1), "90");
+        assert_eq!(rotate_transform("270", 1), "normal");
+        assert_eq!(rotate_transform("normal", -1), "270");
+        assert_eq!(rotate_transform("flipped", 1), "flipped-90");
+        assert_eq!(rotate_transform("flipped-270", 1), "flipped");
+        assert_eq!(parse_transform("flipped-180"), (2, true));
+        assert_eq!(parse_transform("90"), (1, false));
+        assert_eq!(flip_transform("90"), "flipped-90");
+        assert_eq!(flip_transform("flipped"), "normal");
+
+        let moving = rect(100, 0, 1000, 1000);
+        let others = [rect(1920, 0, 1920, 1080)];
+        assert_eq!(next_edge(moving, &others, true, 1), Some(920));
+        assert_eq!(
+            next_edge(rect(920, 0, 1000, 1000), &others, true, 1),
+            Some(1920)
+        );
+        assert_eq!(next_edge(moving, &others, true, -1), None);
+        assert_eq!(next_edge(moving, &others, false, 1), Some(80));
+    }
+
+    #[test]
+    fn test_measure_neighbours() {
+        let moving = rect(0, 0, 1000, 1000);
+        let others = [
+            rect(1100, 200, 1000, 1000),
+            rect(1500, 0, 1000, 1000),
+            rect(200, 1050, 400, 400),
+            rect(3000, 3000, 10, 10),
+        ];
+        let measures = measure_neighbours(moving, &others);
+        assert_eq!(measures.len(), 2);
+        assert_eq!(
+            measures[0],
+            Measure {
+                from: (1000, 600),
+                to: (1100, 600),
+                distance: 100,
+            }
+        );
+        assert_eq!(measures[1].distance, 50);
+        assert_eq!(measures[1].from, (400, 1000));
+        // Touching or overlapping neighbours measure zero or not at all
+        assert_eq!(
+            measure_neighbours(moving, &[rect(1000, 0, 10, 10)])[0].distance,
+            0
+        );
+        assert!(measure_neighbours(moving, &[rect(500, 500, 10, 10)]).is_empty());
+    }
+
+    #[test]
+    fn test_snap_edges_and_grid() {
+        let config = SnapConfig::default();
+        let others = [rect(0, 0, 1920, 1080)];
+
+        // Right next to the neighbour, tops within reach
+        let snapped = snap(rect(1950, 25, 1920, 1080), &others, &config);
+        assert_eq!((snapped.x, snapped.y), (1920, 0));
+        assert!(snapped.guides.contains(&Guide::Vertical(1920)));
+        assert!(snapped.guides.contains(&Guide::Horizontal(0)));
+        assert!(snapped.guides.contains(&Guide::Horizontal(1080)));
+
+        // Too far from anything, falls back to the grid
+        let snapped = snap(rect(2500, 1234, 100, 100), &others, &config);
+        assert_eq!((snapped.x, snapped.y), (2500, 1230));
+        assert!(snapped.guides.is_empty());
+
+        let no_grid = SnapConfig { grid: 0, ..config };
+        let snapped = snap(rect(2503, 1234, 100, 100), &others, &no_grid);
+        assert_eq!((snapped.x, snapped.y), (2503, 1234));
+    }
+
+    #[test]
+    fn test_snap_centre_lines() {
+        let others = [rect(0, 0, 1920, 1080)];
+        let moving = rect(700, 1080, 500, 500);
+        let snapped = snap(moving, &others, &SnapConfig::default());
+        assert_eq!((snapped.x, snapped.y), (710, 1080));
+        assert!(snapped.guides.contains(&Guide::Vertical(960)));
+
+        let edges_only = SnapConfig {
+            centre_lines: false,
+            ..SnapConfig::default()
+        };
+        let snapped = snap(moving, &others, &edges_only);
+        assert_eq!((snapped.x, snapped.y), (700, 1080));
+    }
+
+    #[test]
+    fn test_constraints() {
+        let c: Constraint = "below eDP-1 centre".parse().unwrap();
+        assert_eq!(c.side, Side::Below);
+        assert_eq!(c.to_string(), "below eDP-1 centre");
+        assert!("beside eDP-1 start".parse::<Constraint>().is_err());
+
+        let mut constraints = BTreeMap::new();
+        constraints.insert("DP-1".to_string(), "right-of eDP-1 end".parse().unwrap());
+        constraints.insert("DP-2".to_string(), c);
+        let mut rects = vec![
+            ("DP-2".to_string(), rect(0, 0, 1000, 500)),
+            ("DP-1".to_string(), rect(0, 0, 2560, 1440)),
+            ("eDP-1".to_string(), rect(0, 0, 1920, 1200)),
+        ];
+        solve(&mut rects, &constraints);
+        assert_eq!(rects[0].1, rect(460, 1200, 1000, 500));
+        assert_eq!(rects[1].1, rect(1920, -240, 2560, 1440));
+        assert_eq!(rects[2].1, rect(0, 0, 1920, 1200));
+
+        assert!(depends_on(&constraints, "DP-1", "eDP-1"));
+        assert!(!depends_on(&constraints, "eDP-1", "DP-1"));
+    }
+
+    #[test]
+    fn test_arrange_fresh_dock() {
+        // Every head on a new dock reports 0,0
+        let rects = [
+            rect(0, 0, 1920, 1080),
+            rect(0, 0, 2560, 1440),
+            rect(0, 0, 1920, 1080),
+        ];
+        let row = arrange(&rects, Arrangement::LeftToRight);
+        assert_eq!(row, vec![(0, 0), (1920, 0), (4480, 0)]);
+        let column = arrange(&rects, Arrangement::TopToBottom);
+        assert_eq!(column, vec![(0, 0), (0, 1080), (0, 2520)]);
+    }
+
+    #[test]
+    fn test_arrange_align_and_pack() {
+        let rects = [rect(0, 100, 1920, 1080), rect(1950, 0, 2560, 1440)];
+        assert_eq!(
+            arrange(&rects, Arrangement::AlignBottom),
+            vec![(0, 360), (1950, 0)]
+        );
+        assert_eq!(
+            arrange(&rects, Arrangement::AlignMiddle),
+            vec![(0, 180), (1950, 0)]
+        );
+        assert_eq!(arrange(&rects, Arrangement::Pack), vec![(0, 0), (1920, 0)]);
+    }
+
+    #[test]
+    fn test_spread_for_bezels() {
+        // Two side by side with a third underneath the first
+        let rects = [
+            rect(0, 0, 1000, 500),
+            rect(1000, 0, 1000, 500),
+            rect(0, 500, 1000, 500),
+        ];
+        let spread_rects = spread(&rects, |_, _| 40);
+        assert_eq!(spread_rects, vec![(0, 0), (1040, 0), (0, 540)]);
+
+        let again: Vec<_> = rects
+            .iter()
+            .zip(&spread_rects)
+            .map(|(r, (x, y))| rect(*x, *y, r.w, r.h))
+            .collect();
+        assert_eq!(spread(&again, |_, _| 40), spread_rects);
+        // A wider existing gap is left alone
+        let apart = [rect(0, 0, 1000, 500), rect(1005, 0, 1000, 500)];
+        assert_eq!(spread(&apart, |_, _| 3), vec![(0, 0), (1005, 0)]);
+    }
+
+    #[test]
+    fn test_physical_positions() {
+        // A dense laptop panel left of a large monitor, with a third below
+        // the monitor and a 100 px gap under it
+        let rects = [
+            rect(0, 0, 1920, 1080),
+            rect(1920, 0, 1920, 1080),
+            rect(1920, 1180, 1920, 1080),
+        ];
+        let sizes = [(300.0, 170.0), (600.0, 340.0), (600.0, 340.0)];
+        assert_eq!(
+            physical_positions(&rects, &sizes, 0.25),
+            vec![(0.0, 0.0), (300.0, 0.0), (300.0, 365.0)]
+        );
+    }
+}
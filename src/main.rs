@@ -1,4 +1,8 @@
 mod backend;
+mod error;
+mod history;
+mod layout;
+mod scale;
 mod settings;
 mod ui;
 
@@ -21,6 +25,21 @@ struct Args {
         help = "Whether to auto-append 'source=./monitors.conf' to config.conf (true/false)"
     )]
     auto_append_source: Option<bool>,
+
+    #[arg(
+        long,
+        help = "Distance in logical pixels at which dragged monitors snap to edges"
+    )]
+    snap_threshold: Option<i32>,
+
+    #[arg(long, help = "Grid size used when no edge is in reach, 0 to disable")]
+    snap_grid: Option<i32>,
+
+    #[arg(
+        long,
+        help = "Whether to also snap monitor centres to each other (true/false)"
+    )]
+    snap_centre_lines: Option<bool>,
 }
 
 fn main() -> iced::Result {
@@ -41,11 +60,26 @@ fn main() -> iced::Result {
         app_settings.auto_append_source = append;
         exit_after_args = true;
     }
+    if let Some(threshold) = args.snap_threshold {
+        app_settings.snap_threshold = threshold;
+        exit_after_args = true;
+    }
+    if let Some(grid) = args.snap_grid {
+        app_settings.snap_grid = grid;
+        exit_after_args = true;
+    }
+    if let Some(centre_lines) = args.snap_centre_lines {
+        app_settings.snap_centre_lines = centre_lines;
+        exit_after_args = true;
+    }
 
     if exit_after_args {
         if let Err(e) = app_settings.save() {
             eprintln!("Error saving settings: {}", e);
-            std::process::exit(1);
+            if let Some(hint) = e.guidance() {
+                eprintln!("{}", hint);
+            }
+            std::process::exit(e.exit_code());
         }
         println!("Settings updated successfully.");
         return Ok(());
@@ -69,6 +103,7 @@ fn main() -> iced::Result {
         ui::MangoDisplay::update,
         ui::MangoDisplay::view,
     )
+    .subscription(ui::MangoDisplay::subscription)
     .title("MDisplay")
     .theme(move |_app: &ui::MangoDisplay| custom_theme.clone())
     .window_size(iced::Size::new(1000.0, 700.0))
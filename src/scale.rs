@@ -0,0 +1,106 @@
+// Fractional scales travel over wp_fractional_scale_v1 as multiples of 1/120,
+// so any other value gets rounded by the compositor behind our back.
+pub const DENOMINATOR: i32 = 120;
+
+// The +/- buttons move in 0.05 increments, which is 6/120
+const STEP: i32 = 6;
+const MIN_NUMERATOR: i32 = 12;
+const MAX_CRISP_NUMERATOR: i32 = 3 * DENOMINATOR;
+
+pub fn numerator(scale: f32) -> i32 {
+    ((scale * DENOMINATOR as f32).round() as i32).max(MIN_NUMERATOR)
+}
+
+pub fn from_numerator(n: i32) -> f32 {
+    n as f32 / DENOMINATOR as f32
+}
+
+pub fn snap(scale: f32) -> f32 {
+    from_numerator(numerator(scale))
+}
+
+pub fn step(scale: f32, steps: i32) -> f32 {
+    from_numerator((numerator(scale) + steps * STEP).max(MIN_NUMERATOR))
+}
+
+pub fn logical_size(width: i32, height: i32, scale: f32) -> (f32, f32) {
+    let s = snap(scale);
+    (width as f32 / s, height as f32 / s)
+}
+
+/// A scale is crisp for a mode when both logical dimensions come out integral.
+pub fn is_crisp(width: i32, height: i32, scale: f32) -> bool {
+    let n = numerator(scale);
+    (width * DENOMINATOR) % n == 0 && (height * DENOMINATOR) % n == 0
+}
+
+/// Every crisp scale between 1.0 and 3.0 for the given mode, ascending.
+pub fn crisp_scales(width: i32, height: i32) -> Vec<f32> {
+    (DENOMINATOR..=MAX_CRISP_NUMERATOR)
+        .map(from_numerator)
+        .filter(|s| is_crisp(width, height, *s))
+        .collect()
+}
+
+// wlroots and most toolkits assume 96 PPI at scale 1.0
+pub const REFERENCE_PPI: f32 = 96.0;
+
+/// Pixels per inch along the horizontal axis.
+pub fn pixel_density(width_px: i32, width_mm: u32) -> f32 {
+    width_px as f32 / (width_mm as f32 / 25.4)
+}
+
+/// Picks the crisp scale closest to what the panel density asks for, falling
+/// back to quarter steps when the mode has no crisp scale in range.
+pub fn recommended(width: i32, height: i32, ppi: f32) -> f32 {
+    let ideal = (ppi / REFERENCE_PPI).max(1.0);
+    crisp_scales(width, height)
+        .into_iter()
+        .min_by(|a, b| (a - ideal).abs().total_cmp(&(b - ideal).abs()))
+        .unwrap_or_else(|| snap((ideal * 4.0).round() / 4.0))
+}
+
+/// Formats a scale with enough precision to survive a round trip through `snap`.
+pub fn format(scale: f32) -> String {
+    let s = format!("{:.4}", snap(scale));
+    let s = s.trim_end_matches('0');
+    s.strip_suffix('.').unwrap_or(s).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snap_and_step() {
+        assert_eq!(numerator(1.25), 150);
+        assert_eq!(numerator(1.33), 160);
+        assert_eq!(numerator(step(1.0, 1)), 126);
+        assert_eq!(numerator(step(1.0, -1)), 114);
+        assert_eq!(numerator(step(0.1, -5)), MIN_NUMERATOR);
+        assert_eq!(format(1.875), "1.875");
+        assert_eq!(format(2.0), "2");
+        assert_eq!(numerator(format(4.0 / 3.0).parse().unwrap()), 160);
+    }
+
+    #[test]
+    fn test_crisp_scales() {
+        assert!(is_crisp(1920, 1080, 1.5));
+        assert!(!is_crisp(1920, 1080, 1.1));
+        let crisp = crisp_scales(2560, 1440);
+        assert_eq!(crisp.first(), Some(&1.0));
+        assert!(crisp.contains(&1.25));
+        assert!(crisp.contains(&2.0));
+        assert!(!crisp.contains(&1.75));
+    }
+
+    #[test]
+    fn test_recommended_scale() {
+        // 27" 4K panel, ~163 PPI
+        let ppi = pixel_density(3840, 597);
+        assert_eq!(ppi.round(), 163.0);
+        assert_eq!(numerator(recommended(3840, 2160, ppi)), 200);
+        // 24" 1080p panel, ~92 PPI never goes below 1.0
+        assert_eq!(recommended(1920, 1080, pixel_density(1920, 531)), 1.0);
+    }
+}
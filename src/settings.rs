@@ -1,5 +1,8 @@
 
+use crate::error::{Error, Result};
+use crate::layout::SnapConfig;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::PathBuf;
 
@@ -11,6 +14,34 @@ pub struct AppSettings {
     pub auto_append_source: bool,
     #[serde(default)]
     pub monitors_bak_path: String,
+    #[serde(default = "default_snap_threshold")]
+    pub snap_threshold: i32,
+    #[serde(default = "default_snap_grid")]
+    pub snap_grid: i32,
+    #[serde(default = "default_true")]
+    pub snap_centre_lines: bool,
+    #[serde(default = "default_true")]
+    pub show_rulers: bool,
+    #[serde(default)]
+    pub show_grid: bool,
+    /// Bezel width in millimetres, keyed by output name
+    #[serde(default)]
+    pub bezels_mm: BTreeMap<String, f32>,
+    /// Physical gap between neighbouring panels in millimetres
+    #[serde(default)]
+    pub panel_gap_mm: f32,
+}
+
+fn default_snap_threshold() -> i32 {
+    SnapConfig::default().threshold
+}
+
+fn default_snap_grid() -> i32 {
+    SnapConfig::default().grid
+}
+
+fn default_true() -> bool {
+    true
 }
 
 impl Default for AppSettings {
@@ -20,11 +51,26 @@ impl Default for AppSettings {
             config_conf_path: "~/.config/mango/config.conf".to_string(),
             auto_append_source: true,
             monitors_bak_path: "~/.config/mango/monitors.bak".to_string(),
+            snap_threshold: default_snap_threshold(),
+            snap_grid: default_snap_grid(),
+            snap_centre_lines: true,
+            show_rulers: true,
+            show_grid: false,
+            bezels_mm: BTreeMap::new(),
+            panel_gap_mm: 0.0,
         }
     }
 }
 
 impl AppSettings {
+    pub fn snap_config(&self) -> SnapConfig {
+        SnapConfig {
+            threshold: self.snap_threshold,
+            grid: self.snap_grid,
+            centre_lines: self.snap_centre_lines,
+        }
+    }
+
     fn settings_path() -> PathBuf {
         dirs::config_dir()
             .unwrap_or_else(|| PathBuf::from("~/.config"))
@@ -34,26 +80,25 @@ impl AppSettings {
 
     pub fn load() -> Self {
         let path = Self::settings_path();
-        if path.exists() {
-            if let Ok(contents) = fs::read_to_string(&path) {
-                if let Ok(settings) = serde_json::from_str(&contents) {
-                    return settings;
-                }
-            }
+        if path.exists()
+            && let Ok(contents) = fs::read_to_string(&path)
+            && let Ok(settings) = serde_json::from_str(&contents)
+        {
+            return settings;
         }
         Self::default()
     }
 
-    pub fn save(&self) -> Result<(), String> {
+    pub fn save(&self) -> Result<()> {
         let path = Self::settings_path();
         if let Some(parent) = path.parent() {
-            fs::create_dir_all(parent).map_err(|e| format!("Failed to create settings dir: {}", e))?;
+            fs::create_dir_all(parent).map_err(|e| Error::io("create", parent, e))?;
         }
-        
-        let json = serde_json::to_string_pretty(self)
-            .map_err(|e| format!("Failed to serialize settings: {}", e))?;
-            
-        fs::write(&path, json).map_err(|e| format!("Failed to write settings.json: {}", e))?;
+
+        let json =
+            serde_json::to_string_pretty(self).map_err(|e| Error::Serialize(e.to_string()))?;
+
+        fs::write(&path, json).map_err(|e| Error::io("write", &path, e))?;
         Ok(())
     }
 }
@@ -1,19 +1,51 @@
+use iced::advanced::widget::operation::{Focusable, Outcome};
+use iced::advanced::widget::{Id, Operation, operate};
 use iced::widget::canvas::{self, Action, Cache, Canvas, Event, Geometry, Path, Program};
 use iced::widget::{
     Container, Scrollable, Space, button, checkbox, column, container, pick_list, row, text,
     text_input,
 };
 use iced::{
-    Color, Element, Length, Point, Rectangle, Renderer, Size, Task, Theme, alignment, mouse,
+    Color, Element, Length, Point, Rectangle, Renderer, Size, Subscription, Task, Theme, alignment,
+    keyboard, mouse,
 };
+use std::collections::BTreeMap;
+use std::fmt;
 use std::str::FromStr;
-
-use crate::backend::{Output, OutputMode, wlr_randr_apply, wlr_randr_get_outputs, wlr_randr_restore_default, wlr_randr_save};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::backend::{
+    Mismatch, Output, OutputId, Resolution, TRANSFORMS, compare_applied, load_constraints,
+    merge_outputs, monitorrule_line, wlr_randr_apply, wlr_randr_get_outputs,
+    wlr_randr_restore_default, wlr_randr_save,
+};
+use crate::error::Error;
+use crate::history::History;
+use crate::layout::{self, Align, Arrangement, Constraint, Guide, Issue, Side, SnapConfig};
+use crate::scale;
 
 #[derive(Debug, Clone)]
 pub enum Message {
-    MonitorClicked(usize),
-    MonitorPositioned(usize, i32, i32),
+    MonitorClicked(OutputId),
+    MonitorToggled(OutputId),
+    SelectionSet(Vec<OutputId>, bool),
+    MonitorsPositioned(Vec<(OutputId, i32, i32)>),
+    /// Outputs dropped on the tray, with where they were before the drag
+    Park(Vec<(OutputId, i32, i32)>),
+    /// An output dragged out of the tray, and where it was dropped
+    Unpark(OutputId, i32, i32),
+    Nudge(OutputId, i32, i32),
+    Rotate(OutputId, i32),
+    Flip(OutputId),
+    OriginSet(OutputId),
+    /// Makes the first output show the same area as the second
+    Mirror(OutputId, OutputId),
+    UsePreferredMode(OutputId),
+    UsePreferredModes,
+    CopyMonitorRule(OutputId),
+    DragFinished,
     XChanged(String),
     YChanged(String),
     XInc,
@@ -21,65 +53,702 @@ pub enum Message {
     YInc,
     YDec,
     ScaleChanged(String),
+    /// A scale chosen from the crisp scales list
+    ScalePicked(String),
+    /// Enter pressed in one of the sidebar's fields
+    EditFinished,
     ScaleInc,
     ScaleDec,
+    UseRecommendedScale,
+    MatchPhysicalSize,
     EnabledToggled(bool),
+    OriginToggled(bool),
+    PlacementSideSelected(Side),
+    PlacementAnchorSelected(String),
+    PlacementAlignSelected(AlignChoice),
+    PlacementCleared,
     ResolutionSelected(usize),
+    ResolutionPicked(Resolution),
+    ResolutionStep(i32),
+    RefreshStep(i32),
     TransformSelected(String),
+    Arrange(Arrangement),
+    Undo,
+    Redo,
+    /// Ctrl+Z or Ctrl+Y, which only reach the layout when no text field has
+    /// keyboard focus
+    HistoryShortcut(bool),
+    HistoryJump(usize),
+    FitView,
+    RulersToggled(bool),
+    GridToggled(bool),
+    PhysicalViewToggled(bool),
+    BezelChanged(String),
+    PanelGapChanged(String),
+    CompensateBezels,
     ApplyClicked,
     SaveClicked,
     RestoreDefaultClicked,
+    RescanClicked,
+    /// A fresh read of the outputs from the background poll
+    OutputsScanned(Result<Vec<Output>, Error>),
+    RescanFinished(Result<Vec<Output>, Error>),
+    ApplyFinished(Result<Applied, Error>),
+    /// Problems that didn't stop the save, or why it failed
+    SaveFinished(Result<Vec<Error>, Error>),
+    RestoreFinished(Result<(), Error>),
+    CancelClicked,
+    /// Keeps the progress indicator moving while something runs
+    BusyTick,
+}
+
+/// The outcome of a successful apply.
+#[derive(Debug, Clone)]
+pub struct Applied {
+    /// Names of the outputs that had something to change
+    touched: Vec<String>,
+    /// What the compositor reports afterwards
+    live: Vec<Output>,
+    /// Where that differs from what was asked for
+    mismatches: Vec<Mismatch>,
+}
+
+/// An alignment option labelled for the side it applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AlignChoice {
+    align: Align,
+    side: Side,
+}
+
+impl fmt::Display for AlignChoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.align.label(self.side))
+    }
+}
+
+/// A refresh rate offered at the current resolution, pointing at its mode.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RefreshChoice {
+    mode: usize,
+    rate: Option<f32>,
+}
+
+impl fmt::Display for RefreshChoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.rate {
+            Some(rate) => write!(f, "{:.3}", rate),
+            None => f.write_str("Any"),
+        }
+    }
+}
+
+// How often the outputs are re-read while the window is open
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+const SPINNER: [&str; 4] = ["|", "/", "-", "\\"];
+const SPINNER_INTERVAL: Duration = Duration::from_millis(150);
+
+/// A backend operation running in the background.
+struct Busy {
+    /// What's happening, as shown next to the spinner
+    label: &'static str,
+    started: Instant,
+    /// Set to ask the operation to stop, `None` if it can't be stopped
+    cancel: Option<Arc<AtomicBool>>,
+    /// Layout warnings to mention once it's done
+    warnings: Option<String>,
+}
+
+impl Busy {
+    fn cancelled(&self) -> bool {
+        self.cancel
+            .as_ref()
+            .is_some_and(|cancel| cancel.load(Ordering::Relaxed))
+    }
+}
+
+/// The parts of the layout that undo and redo bring back.
+struct Snapshot {
+    outputs: Vec<Output>,
+    constraints: BTreeMap<String, Constraint>,
+    origin: Option<String>,
 }
 
 pub struct MangoDisplay {
     outputs: Vec<Output>,
-    selected_output_idx: Option<usize>,
+    /// What wlr-randr last reported, to tell unsaved edits apart from
+    /// changes made outside the app
+    live: Vec<Output>,
+    history: History<Snapshot>,
+    /// Name of the output whose top-left corner is pinned to 0,0
+    origin: Option<String>,
+    /// Relative placements, keyed by the name of the output being placed
+    constraints: BTreeMap<String, Constraint>,
+    // Bumped to make the canvas drop its zoom and pan and refit the layout
+    fit_generation: u64,
+    /// The output shown in the sidebar
+    selected: Option<OutputId>,
+    /// Every selected output, including the one shown in the sidebar
+    selection: Vec<OutputId>,
     layout_cache: Cache,
     x_input: String,
     y_input: String,
     scale_input: String,
+    bezel_input: String,
+    panel_gap_input: String,
+    /// Draw panels at their real size instead of their logical size
+    physical_view: bool,
+    busy: Option<Busy>,
+    /// Settings the compositor didn't take on the last apply
+    apply_report: Vec<Mismatch>,
     pub settings: crate::settings::AppSettings,
     status_message: Option<String>,
 }
 
 impl Default for MangoDisplay {
     fn default() -> Self {
-        let outputs = wlr_randr_get_outputs().unwrap_or_default();
-        let selected_output_idx = if !outputs.is_empty() { Some(0) } else { None };
+        let settings = crate::settings::AppSettings::load();
+        let constraints = load_constraints(&settings);
+        Self::new(wlr_randr_get_outputs(), settings, constraints)
+    }
+}
+
+impl MangoDisplay {
+    /// Starts from an output query that has already run, so nothing here
+    /// touches wlr-randr or the disk.
+    fn new(
+        query: Result<Vec<Output>, Error>,
+        settings: crate::settings::AppSettings,
+        constraints: BTreeMap<String, Constraint>,
+    ) -> Self {
+        let (mut outputs, query_error) = match query {
+            Ok(outputs) => (outputs, None),
+            Err(e) => (Vec::new(), Some(failure("Output query", &e))),
+        };
+        // Outputs that are off don't report a current mode, which is expected
+        let stale: Vec<String> = outputs
+            .iter_mut()
+            .filter_map(|o| (o.settle_mode() && o.enabled).then(|| o.name.clone()))
+            .collect();
+        let selected = outputs.first().map(Output::id);
         let mut app = Self {
+            live: outputs.clone(),
             outputs,
-            selected_output_idx,
+            history: History::default(),
+            origin: None,
+            constraints,
+            fit_generation: 0,
+            selection: selected.iter().cloned().collect(),
+            selected,
             layout_cache: Cache::default(),
             x_input: String::new(),
             y_input: String::new(),
             scale_input: String::new(),
-            settings: crate::settings::AppSettings::load(),
-            status_message: None,
+            bezel_input: String::new(),
+            panel_gap_input: settings.panel_gap_mm.to_string(),
+            physical_view: false,
+            busy: None,
+            apply_report: Vec::new(),
+            settings,
+            status_message: query_error.or_else(|| {
+                (!stale.is_empty()).then(|| {
+                    format!(
+                        "No current mode reported for {}, using the preferred mode instead",
+                        stale.join(", ")
+                    )
+                })
+            }),
         };
+        app.solve_constraints();
         app.update_inputs_for_selection();
         app
     }
 }
 
+/// Re-reads the outputs every `POLL_INTERVAL`, reporting only when something
+/// changed. wlr-randr can't wait for hotplug events, so this polls.
+fn watch_outputs() -> impl iced::futures::Stream<Item = Message> {
+    use iced::futures::SinkExt;
+
+    iced::stream::channel(1, async |mut sender| {
+        std::thread::spawn(move || {
+            let mut last = wlr_randr_get_outputs();
+            loop {
+                std::thread::sleep(POLL_INTERVAL);
+                let scanned = wlr_randr_get_outputs();
+                if scanned == last {
+                    continue;
+                }
+                last = scanned.clone();
+                // Fails once the subscription is dropped, which ends the poll
+                let sent = sender.send(Message::OutputsScanned(scanned));
+                if iced::futures::executor::block_on(sent).is_err() {
+                    break;
+                }
+            }
+        });
+    })
+}
+
+fn busy_ticks() -> impl iced::futures::Stream<Item = Message> {
+    use iced::futures::SinkExt;
+
+    iced::stream::channel(1, async |mut sender| {
+        std::thread::spawn(move || {
+            loop {
+                std::thread::sleep(SPINNER_INTERVAL);
+                if iced::futures::executor::block_on(sender.send(Message::BusyTick)).is_err() {
+                    break;
+                }
+            }
+        });
+    })
+}
+
+/// Finds out whether any widget, such as one of the sidebar's text fields,
+/// has keyboard focus.
+fn focus_held() -> impl Operation<bool> {
+    struct FocusHeld(bool);
+
+    impl Operation<bool> for FocusHeld {
+        fn traverse(&mut self, operate: &mut dyn FnMut(&mut dyn Operation<bool>)) {
+            operate(self);
+        }
+
+        fn focusable(&mut self, _id: Option<&Id>, _bounds: Rectangle, state: &mut dyn Focusable) {
+            self.0 |= state.is_focused();
+        }
+
+        fn finish(&self) -> Outcome<bool> {
+            Outcome::Some(self.0)
+        }
+    }
+
+    FocusHeld(false)
+}
+
+/// Runs blocking backend work on a thread of its own, so the window keeps
+/// drawing while wlr-randr or the disk takes its time. A thread that panics
+/// drops its end of the channel, which comes back as `WorkerLost`.
+async fn off_thread<T: Send + 'static>(
+    work: impl FnOnce() -> Result<T, Error> + Send + 'static,
+) -> Result<T, Error> {
+    let (sender, receiver) = iced::futures::channel::oneshot::channel();
+    std::thread::spawn(move || {
+        let _ = sender.send(work());
+    });
+    receiver.await.unwrap_or(Err(Error::WorkerLost))
+}
+
+/// A status line for a failed operation, with advice when there is some.
+fn failure(action: &str, e: &Error) -> String {
+    match e.guidance() {
+        Some(hint) => format!("{} error: {}. {}", action, e, hint),
+        None => format!("{} error: {}", action, e),
+    }
+}
+
+/// The entry `step` places along from `current`, stopping at either end.
+fn step_through<T>(items: &[T], current: Option<usize>, step: i32) -> Option<&T> {
+    let last = items.len().checked_sub(1)? as i32;
+    let next = match current {
+        Some(i) => (i as i32 + step).clamp(0, last),
+        None => 0,
+    };
+    (Some(next as usize) != current).then(|| &items[next as usize])
+}
+
 impl MangoDisplay {
+    fn index_of(&self, id: &OutputId) -> Option<usize> {
+        self.outputs.iter().position(|o| o.id() == *id)
+    }
+
+    fn selected_idx(&self) -> Option<usize> {
+        self.index_of(self.selected.as_ref()?)
+    }
+
+    /// Looks up where each moved output currently sits, dropping any that
+    /// have gone away since the message was sent.
+    fn resolve(&self, moves: Vec<(OutputId, i32, i32)>) -> Vec<(usize, i32, i32)> {
+        moves
+            .into_iter()
+            .filter_map(|(id, x, y)| Some((self.index_of(&id)?, x, y)))
+            .collect()
+    }
+
+    /// Drops selected outputs that no longer exist, moving the sidebar to
+    /// another output if its own went away.
+    fn forget_missing(&mut self) {
+        let outputs = &self.outputs;
+        self.selection
+            .retain(|id| outputs.iter().any(|o| o.id() == *id));
+        if self.selected_idx().is_none() {
+            self.selected = self
+                .selection
+                .first()
+                .cloned()
+                .or_else(|| self.outputs.last().map(Output::id));
+        }
+    }
+
     fn update_inputs_for_selection(&mut self) {
-        if let Some(idx) = self.selected_output_idx {
+        if let Some(idx) = self.selected_idx() {
             let out = &self.outputs[idx];
             self.x_input = out.position.0.to_string();
             self.y_input = out.position.1.to_string();
-            self.scale_input = format!("{:.2}", out.scale);
+            self.scale_input = scale::format(out.scale);
+            self.bezel_input = self
+                .settings
+                .bezels_mm
+                .get(&out.name)
+                .map_or("0".to_string(), f32::to_string);
         }
     }
 
-    fn normalize_positions(&mut self) {
-        let min_x = self.outputs.iter().map(|o| o.position.0).min().unwrap_or(0);
-        let min_y = self.outputs.iter().map(|o| o.position.1).min().unwrap_or(0);
+    /// Records the current layout before an edit.
+    fn checkpoint(&mut self, label: String, coalesce: bool) {
+        self.history.checkpoint(label, coalesce, || Snapshot {
+            outputs: self.outputs.clone(),
+            constraints: self.constraints.clone(),
+            origin: self.origin.clone(),
+        });
+    }
+
+    /// A checkpoint for a drag, which stays one gesture until DragFinished.
+    fn drag_checkpoint(&mut self, label: String) {
+        self.history.drag_checkpoint(label, || Snapshot {
+            outputs: self.outputs.clone(),
+            constraints: self.constraints.clone(),
+            origin: self.origin.clone(),
+        });
+    }
+
+    /// Runs a history step on the current layout.
+    fn step_history<R>(
+        &mut self,
+        step: impl FnOnce(&mut History<Snapshot>, &mut Snapshot) -> R,
+    ) -> R {
+        let mut current = Snapshot {
+            outputs: std::mem::take(&mut self.outputs),
+            constraints: std::mem::take(&mut self.constraints),
+            origin: self.origin.take(),
+        };
+        let result = step(&mut self.history, &mut current);
+        self.outputs = current.outputs;
+        self.constraints = current.constraints;
+        self.origin = current.origin;
+        result
+    }
+
+    fn undo(&mut self) -> bool {
+        self.step_history(History::undo)
+    }
+
+    fn redo(&mut self) -> bool {
+        self.step_history(History::redo)
+    }
 
+    /// Sets the scale of every target from what was typed or picked.
+    fn set_scale(&mut self, val: String, coalesce: bool) {
+        if let Ok(v) = f32::from_str(&val)
+            && v > 0.1
+        {
+            self.checkpoint(self.targets_label("Scale"), coalesce);
+            for idx in self.targets() {
+                self.outputs[idx].scale = scale::snap(v);
+            }
+            self.layout_cache.clear();
+        }
+        self.scale_input = val;
+    }
+
+    fn history_restored(&mut self) {
+        self.history.end_gesture();
+        self.forget_missing();
+        self.update_inputs_for_selection();
+        self.layout_cache.clear();
+    }
+
+    /// Millimetres per logical pixel for the physical view: the median across
+    /// panels, so positions stay roughly where the layout puts them.
+    fn mm_per_px(&self) -> f32 {
+        let mut densities: Vec<f32> = self
+            .outputs
+            .iter()
+            .filter(|o| o.physical_size.is_some())
+            .map(|o| 1.0 / layout::px_per_mm(o))
+            .collect();
+        densities.sort_by(f32::total_cmp);
+        densities
+            .get(densities.len() / 2)
+            .copied()
+            .unwrap_or(25.4 / scale::REFERENCE_PPI)
+    }
+
+    fn select_mode(&mut self, idx: usize, mode: usize) {
+        if mode >= self.outputs[idx].modes.len() || self.outputs[idx].modes[mode].current {
+            return;
+        }
+        self.checkpoint(format!("Change mode of {}", self.outputs[idx].name), false);
+        for (i, m) in self.outputs[idx].modes.iter_mut().enumerate() {
+            m.current = i == mode;
+        }
+        self.layout_cache.clear();
+    }
+
+    /// The mode to switch to for a resolution: the preferred one if it has
+    /// that size, otherwise whichever rate is closest to the current one.
+    fn mode_for_resolution(&self, idx: usize, width: i32, height: i32) -> Option<usize> {
+        let out = &self.outputs[idx];
+        let rates = out.refresh_rates(width, height);
+        let current = out.current_mode().and_then(|cm| cm.refresh_rate);
+        let distance =
+            |m: usize| (out.modes[m].refresh_rate.unwrap_or(0.0) - current.unwrap_or(60.0)).abs();
+        rates
+            .iter()
+            .copied()
+            .find(|m| out.modes[*m].preferred)
+            .or_else(|| {
+                rates
+                    .into_iter()
+                    .min_by(|a, b| distance(*a).total_cmp(&distance(*b)))
+            })
+    }
+
+    fn save_settings(&mut self) {
+        if let Err(e) = self.settings.save() {
+            self.status_message = Some(failure("Settings", &e));
+        }
+    }
+
+    /// Merges a fresh read of the outputs into the layout, keeping unsaved
+    /// edits, and says what was plugged in or unplugged.
+    fn rescan(&mut self, scanned: Result<Vec<Output>, Error>, manual: bool) {
+        let mut scanned = match scanned {
+            Ok(scanned) => scanned,
+            Err(e) => {
+                self.status_message = Some(failure("Rescan", &e));
+                return;
+            }
+        };
+        for out in &mut scanned {
+            out.settle_mode();
+        }
+        let connected = scanned
+            .iter()
+            .filter(|o| self.index_of(&o.id()).is_none())
+            .map(|o| format!("{} connected", o.name));
+        let disconnected = self
+            .outputs
+            .iter()
+            .filter(|o| !scanned.iter().any(|s| s.name == o.name))
+            .map(|o| format!("{} disconnected", o.name));
+        let changes: Vec<String> = connected.chain(disconnected).collect();
+
+        let merged = merge_outputs(&self.outputs, &self.live, &scanned);
+        // Undo shouldn't bring back a monitor that has been unplugged
+        for snapshot in self.history.states_mut() {
+            snapshot.outputs = merge_outputs(&snapshot.outputs, &self.live, &scanned);
+        }
+        let changed = merged != self.outputs;
+        self.outputs = merged;
+        self.live = scanned;
+
+        if !changes.is_empty() {
+            self.status_message = Some(format!("Outputs changed: {}", changes.join(", ")));
+            self.fit_generation += 1;
+        } else if manual {
+            self.status_message = Some(if changed {
+                "Picked up changes made outside MDisplay".to_string()
+            } else {
+                "No changes found".to_string()
+            });
+        }
+        if changed {
+            self.forget_missing();
+            self.update_inputs_for_selection();
+            self.layout_cache.clear();
+        }
+    }
+
+    pub fn subscription(&self) -> Subscription<Message> {
+        let shortcuts = keyboard::listen().filter_map(|event| match event {
+            keyboard::Event::KeyPressed {
+                key: keyboard::Key::Character(c),
+                modifiers,
+                ..
+            } if modifiers.command() => match c.as_str() {
+                "z" | "Z" if modifiers.shift() => Some(Message::HistoryShortcut(true)),
+                "z" => Some(Message::HistoryShortcut(false)),
+                "y" => Some(Message::HistoryShortcut(true)),
+                _ => None,
+            },
+            _ => None,
+        });
+        let ticks = if self.busy.is_some() {
+            Subscription::run(busy_ticks)
+        } else {
+            Subscription::none()
+        };
+        Subscription::batch([shortcuts, Subscription::run(watch_outputs), ticks])
+    }
+
+    /// Drops the mismatches from the last apply that no longer hold, because
+    /// the output was edited since or the compositor now reports otherwise.
+    fn prune_apply_report(&mut self) {
+        if self.apply_report.is_empty() {
+            return;
+        }
+        let current = compare_applied(&self.outputs, &self.live);
+        self.apply_report.retain(|m| current.contains(m));
+    }
+
+    /// Recomputes the position of every output placed relative to another.
+    /// Runs after each update so sizes changing anywhere pull neighbours along.
+    fn solve_constraints(&mut self) {
+        if self.constraints.is_empty() {
+            return;
+        }
+        let enabled: Vec<usize> = (0..self.outputs.len())
+            .filter(|i| self.outputs[*i].enabled)
+            .collect();
+        let mut rects: Vec<_> = enabled
+            .iter()
+            .map(|i| {
+                let out = &self.outputs[*i];
+                (out.name.clone(), layout::logical_rect(out))
+            })
+            .collect();
+        layout::solve(&mut rects, &self.constraints);
+
+        let mut changed = false;
+        for (i, (_, rect)) in enabled.into_iter().zip(rects) {
+            if self.outputs[i].position != (rect.x, rect.y) {
+                self.outputs[i].position = (rect.x, rect.y);
+                changed = true;
+            }
+        }
+        if changed {
+            self.update_inputs_for_selection();
+            self.layout_cache.clear();
+        }
+    }
+
+    /// Drops the relative placement of an output the user moved by hand,
+    /// otherwise the solver would snap it straight back.
+    fn release_constraint(&mut self, idx: usize) {
+        let name = &self.outputs[idx].name;
+        if self.constraints.remove(name).is_some() {
+            self.status_message = Some(format!(
+                "{} is no longer placed relative to another output",
+                name
+            ));
+        }
+    }
+
+    fn set_constraint(&mut self, idx: usize, edit: impl FnOnce(&mut Constraint)) {
+        let name = self.outputs[idx].name.clone();
+        let mut constraint = match self.constraints.get(&name) {
+            Some(c) => c.clone(),
+            None => {
+                let Some(anchor) = self.anchor_candidates(idx).into_iter().next() else {
+                    return;
+                };
+                Constraint {
+                    anchor,
+                    side: Side::RightOf,
+                    align: Align::Start,
+                }
+            }
+        };
+        edit(&mut constraint);
+        self.checkpoint(format!("Place {}", name), false);
+        self.constraints.insert(name, constraint);
+    }
+
+    /// Outputs `idx` can be placed against without creating a loop.
+    fn anchor_candidates(&self, idx: usize) -> Vec<String> {
+        let name = &self.outputs[idx].name;
+        self.outputs
+            .iter()
+            .filter(|o| o.enabled && &o.name != name)
+            .filter(|o| !layout::depends_on(&self.constraints, &o.name, name))
+            .map(|o| o.name.clone())
+            .collect()
+    }
+
+    /// Drops the relative placements that would move one of `enabled` away
+    /// from where it is now, and returns how many went.
+    fn drop_broken_constraints(&mut self, enabled: &[usize]) -> usize {
+        let placed: Vec<_> = enabled
+            .iter()
+            .map(|i| {
+                let out = &self.outputs[*i];
+                (out.name.clone(), layout::logical_rect(out))
+            })
+            .collect();
+        let before = self.constraints.len();
+        self.constraints.retain(|name, c| {
+            let mut rects = placed.clone();
+            layout::solve(&mut rects, &BTreeMap::from([(name.clone(), c.clone())]));
+            rects == placed
+        });
+        before - self.constraints.len()
+    }
+
+    /// Outputs that sidebar edits apply to.
+    fn targets(&self) -> Vec<usize> {
+        if self.selection.is_empty() {
+            self.selected_idx().into_iter().collect()
+        } else {
+            self.selection
+                .iter()
+                .filter_map(|id| self.index_of(id))
+                .collect()
+        }
+    }
+
+    fn moves_label(&self, verb: &str, moves: &[(usize, i32, i32)]) -> String {
+        match moves {
+            [(idx, _, _)] => format!("{} {}", verb, self.outputs[*idx].name),
+            _ => format!("{} {} outputs", verb, moves.len()),
+        }
+    }
+
+    fn targets_label(&self, verb: &str) -> String {
+        match self.targets()[..] {
+            [idx] => format!("{} {}", verb, self.outputs[idx].name),
+            ref many => format!("{} {} outputs", verb, many.len()),
+        }
+    }
+
+    fn origin_idx(&self) -> Option<usize> {
+        let name = self.origin.as_ref()?;
+        self.outputs
+            .iter()
+            .position(|o| &o.name == name && o.enabled)
+    }
+
+    /// Pins the origin output to 0,0, or without one just pulls the layout
+    /// out of negative space. Editing happens in free coordinates, this runs
+    /// right before the layout is handed to the compositor.
+    fn normalize_positions(&mut self) {
         let mut changed = false;
-        let offset_x = if min_x < 0 { -min_x } else { 0 };
-        let offset_y = if min_y < 0 { -min_y } else { 0 };
+        let (offset_x, offset_y) = match self.origin_idx() {
+            Some(idx) => {
+                let (x, y) = self.outputs[idx].position;
+                (-x, -y)
+            }
+            None => {
+                let enabled = self.outputs.iter().filter(|o| o.enabled);
+                let min_x = enabled.clone().map(|o| o.position.0).min().unwrap_or(0);
+                let min_y = enabled.map(|o| o.position.1).min().unwrap_or(0);
+                (-min_x.min(0), -min_y.min(0))
+            }
+        };
 
-        if offset_x > 0 || offset_y > 0 {
+        if offset_x != 0 || offset_y != 0 {
+            self.checkpoint("Move layout to 0,0".to_string(), false);
             for out in &mut self.outputs {
                 out.position.0 += offset_x;
                 out.position.1 += offset_y;
@@ -93,143 +762,824 @@ impl MangoDisplay {
         }
     }
 
+    fn validate_layout(&self) -> Vec<Issue> {
+        let rects: Vec<_> = self
+            .outputs
+            .iter()
+            .enumerate()
+            .filter(|(_, o)| o.enabled)
+            .map(|(i, o)| (i, layout::logical_rect(o)))
+            .collect();
+        layout::validate(&rects, |a, b| self.seam_px(a, b))
+    }
+
+    /// The gap bezel compensation leaves between two outputs, in logical
+    /// pixels. Each side is converted at its own panel's density, the gap
+    /// between the panels at the average of the two.
+    fn seam_px(&self, a: usize, b: usize) -> i32 {
+        let bezel = |i: usize| {
+            let out = &self.outputs[i];
+            let mm = self
+                .settings
+                .bezels_mm
+                .get(&out.name)
+                .copied()
+                .unwrap_or(0.0);
+            (mm, layout::px_per_mm(out))
+        };
+        let (bezel_a, density_a) = bezel(a);
+        let (bezel_b, density_b) = bezel(b);
+        (bezel_a * density_a
+            + bezel_b * density_b
+            + self.settings.panel_gap_mm * (density_a + density_b) / 2.0)
+            .round() as i32
+    }
+
+    fn describe_issue(&self, issue: &Issue) -> String {
+        let name = |i: &usize| self.outputs[*i].name.as_str();
+        match issue {
+            Issue::Overlap(a, b) => format!("{} overlaps {}", name(a), name(b)),
+            Issue::Gap(a, b, px) => {
+                format!("{}px gap between {} and {}", px, name(a), name(b))
+            }
+            Issue::Island(members) => format!(
+                "{} cannot be reached from the rest of the layout",
+                members.iter().map(name).collect::<Vec<_>>().join(", ")
+            ),
+        }
+    }
+
+    /// Refuses layouts the compositor would render wrong and summarises the
+    /// rest, so Apply and Save can mention them alongside their own result.
+    fn check_layout(&self) -> Result<Option<String>, String> {
+        let issues = self.validate_layout();
+        if let Some(issue) = issues.iter().find(|i| i.is_blocking()) {
+            return Err(self.describe_issue(issue));
+        }
+        if issues.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(
+                issues
+                    .iter()
+                    .map(|i| self.describe_issue(i))
+                    .collect::<Vec<_>>()
+                    .join("; "),
+            ))
+        }
+    }
+
     pub fn update(&mut self, message: Message) -> Task<Message> {
         match message {
-            Message::MonitorClicked(idx) => {
-                self.selected_output_idx = Some(idx);
+            Message::MonitorClicked(id) => {
+                // Clicking inside a multi-selection only moves the focus, so
+                // the group can still be dragged as a whole
+                if !self.selection.contains(&id) {
+                    self.selection = vec![id.clone()];
+                }
+                self.selected = Some(id);
+                self.history.end_gesture();
                 self.update_inputs_for_selection();
                 self.layout_cache.clear();
             }
-            Message::MonitorPositioned(idx, x, y) => {
-                self.outputs[idx].position = (x, y);
-                if Some(idx) == self.selected_output_idx {
-                    self.update_inputs_for_selection();
+            Message::MonitorToggled(id) => {
+                if let Some(pos) = self.selection.iter().position(|i| *i == id) {
+                    self.selection.remove(pos);
+                    if self.selected.as_ref() == Some(&id) {
+                        self.selected = self.selection.last().cloned();
+                    }
+                } else {
+                    self.selection.push(id.clone());
+                    self.selected = Some(id);
+                }
+                self.history.end_gesture();
+                self.update_inputs_for_selection();
+                self.layout_cache.clear();
+            }
+            Message::SelectionSet(ids, additive) => {
+                if !additive {
+                    self.selection.clear();
+                }
+                for id in ids {
+                    if !self.selection.contains(&id) {
+                        self.selection.push(id);
+                    }
+                }
+                if self
+                    .selected
+                    .as_ref()
+                    .is_none_or(|id| !self.selection.contains(id))
+                {
+                    self.selected = self.selection.first().cloned();
+                }
+                self.history.end_gesture();
+                self.update_inputs_for_selection();
+                self.layout_cache.clear();
+            }
+            Message::MonitorsPositioned(moves) => {
+                let moves = self.resolve(moves);
+                if moves.is_empty() {
+                    return Task::none();
+                }
+                let label = self.moves_label("Move", &moves);
+                self.drag_checkpoint(label);
+                for (idx, x, y) in moves {
+                    self.release_constraint(idx);
+                    self.outputs[idx].position = (x, y);
+                }
+                self.update_inputs_for_selection();
+                self.layout_cache.clear();
+            }
+            Message::Nudge(id, dx, dy) => {
+                let Some(idx) = self.index_of(&id) else {
+                    return Task::none();
+                };
+                self.checkpoint(format!("Nudge {}", self.outputs[idx].name), false);
+                self.release_constraint(idx);
+                let (x, y) = self.outputs[idx].position;
+                self.outputs[idx].position = (x + dx, y + dy);
+                self.update_inputs_for_selection();
+                self.layout_cache.clear();
+            }
+            Message::Rotate(id, quarter_turns) => {
+                let Some(idx) = self.index_of(&id) else {
+                    return Task::none();
+                };
+                self.checkpoint(format!("Rotate {}", self.outputs[idx].name), false);
+                self.outputs[idx].transform =
+                    layout::rotate_transform(&self.outputs[idx].transform, quarter_turns);
+                self.layout_cache.clear();
+            }
+            Message::Flip(id) => {
+                let Some(idx) = self.index_of(&id) else {
+                    return Task::none();
+                };
+                self.checkpoint(format!("Flip {}", self.outputs[idx].name), false);
+                self.outputs[idx].transform = layout::flip_transform(&self.outputs[idx].transform);
+                self.layout_cache.clear();
+            }
+            Message::OriginSet(id) => {
+                let name = id.to_string();
+                if self.origin.as_ref() != Some(&name) {
+                    self.checkpoint(format!("Make {} the origin", name), false);
+                    self.origin = Some(name);
+                    self.layout_cache.clear();
+                }
+            }
+            Message::Mirror(id, target) => {
+                let (Some(idx), Some(target)) = (self.index_of(&id), self.index_of(&target)) else {
+                    return Task::none();
+                };
+                let name = self.outputs[idx].name.clone();
+                let target_name = self.outputs[target].name.clone();
+                self.checkpoint(format!("Mirror {} to {}", name, target_name), false);
+                self.release_constraint(idx);
+                // Scale so both come out the same logical width
+                let own = layout::logical_rect(&self.outputs[idx]);
+                let wanted = layout::logical_rect(&self.outputs[target]);
+                let out = &mut self.outputs[idx];
+                out.scale = scale::snap(out.scale * own.w as f32 / wanted.w.max(1) as f32);
+                out.position = (wanted.x, wanted.y);
+                self.status_message =
+                    (layout::logical_rect(&self.outputs[idx]) != wanted).then(|| {
+                        format!(
+                            "{} can't mirror {} exactly, their shapes differ",
+                            name, target_name
+                        )
+                    });
+                self.update_inputs_for_selection();
+                self.layout_cache.clear();
+            }
+            Message::UsePreferredMode(id) => {
+                let Some(idx) = self.index_of(&id) else {
+                    return Task::none();
+                };
+                let Some(preferred) = self.outputs[idx].modes.iter().position(|m| m.preferred)
+                else {
+                    self.status_message = Some(format!(
+                        "{} doesn't report a preferred mode",
+                        self.outputs[idx].name
+                    ));
+                    return Task::none();
+                };
+                self.select_mode(idx, preferred);
+            }
+            Message::UsePreferredModes => {
+                self.checkpoint("Use preferred modes".to_string(), false);
+                for out in &mut self.outputs {
+                    if let Some(preferred) = out.modes.iter().position(|m| m.preferred) {
+                        for (i, m) in out.modes.iter_mut().enumerate() {
+                            m.current = i == preferred;
+                        }
+                    }
+                }
+                self.layout_cache.clear();
+            }
+            Message::CopyMonitorRule(id) => {
+                let Some(idx) = self.index_of(&id) else {
+                    return Task::none();
+                };
+                match monitorrule_line(&self.outputs[idx]) {
+                    Ok(line) => {
+                        self.status_message = Some(format!("Copied the monitorrule for {}", id));
+                        return iced::clipboard::write(line);
+                    }
+                    Err(e) => self.status_message = Some(failure("Copy", &e)),
+                }
+            }
+            Message::Park(moves) => {
+                let moves = self.resolve(moves);
+                let left_enabled = self
+                    .outputs
+                    .iter()
+                    .enumerate()
+                    .any(|(i, o)| o.enabled && !moves.iter().any(|(j, _, _)| *j == i));
+                // The drag already saved the layout from before it started, so
+                // relabel that entry rather than recording the half-way state
+                let dragged = self
+                    .history
+                    .take_gesture()
+                    .is_some_and(|label| label == self.moves_label("Move", &moves));
+                if left_enabled {
+                    let label = self.moves_label("Disable", &moves);
+                    if !(dragged && self.history.relabel(&label)) {
+                        self.checkpoint(label, false);
+                    }
+                } else {
+                    self.status_message =
+                        Some("At least one output has to stay enabled".to_string());
                 }
+                for (idx, x, y) in moves {
+                    self.outputs[idx].position = (x, y);
+                    if left_enabled {
+                        self.outputs[idx].enabled = false;
+                    }
+                }
+                self.update_inputs_for_selection();
                 self.layout_cache.clear();
             }
+            Message::Unpark(id, x, y) => {
+                let Some(idx) = self.index_of(&id) else {
+                    return Task::none();
+                };
+                self.checkpoint(format!("Enable {}", self.outputs[idx].name), false);
+                self.release_constraint(idx);
+                self.outputs[idx].enabled = true;
+                self.outputs[idx].position = (x, y);
+                self.update_inputs_for_selection();
+                self.layout_cache.clear();
+            }
+            Message::DragFinished | Message::EditFinished => {
+                self.history.end_gesture();
+            }
             Message::XChanged(val) => {
                 self.x_input = val.clone();
-                if let (Some(idx), Ok(mut v)) = (self.selected_output_idx, i32::from_str(&val)) {
-                    if v < 0 {
-                        v = 0;
-                    }
+                if let (Some(idx), Ok(v)) = (self.selected_idx(), i32::from_str(&val)) {
+                    self.checkpoint(format!("Edit x of {}", self.outputs[idx].name), true);
+                    self.release_constraint(idx);
                     self.outputs[idx].position.0 = v;
                     self.layout_cache.clear();
                 }
             }
             Message::YChanged(val) => {
                 self.y_input = val.clone();
-                if let (Some(idx), Ok(mut v)) = (self.selected_output_idx, i32::from_str(&val)) {
-                    if v < 0 {
-                        v = 0;
-                    }
+                if let (Some(idx), Ok(v)) = (self.selected_idx(), i32::from_str(&val)) {
+                    self.checkpoint(format!("Edit y of {}", self.outputs[idx].name), true);
+                    self.release_constraint(idx);
                     self.outputs[idx].position.1 = v;
                     self.layout_cache.clear();
                 }
             }
             Message::XInc => {
-                if let Some(idx) = self.selected_output_idx {
+                if let Some(idx) = self.selected_idx() {
+                    self.checkpoint(format!("Nudge {}", self.outputs[idx].name), false);
+                    self.release_constraint(idx);
                     self.outputs[idx].position.0 += 1;
                     self.update_inputs_for_selection();
                     self.layout_cache.clear();
                 }
             }
             Message::XDec => {
-                if let Some(idx) = self.selected_output_idx {
-                    if self.outputs[idx].position.0 > 0 {
-                        self.outputs[idx].position.0 -= 1;
-                        self.update_inputs_for_selection();
-                        self.layout_cache.clear();
-                    }
+                if let Some(idx) = self.selected_idx() {
+                    self.checkpoint(format!("Nudge {}", self.outputs[idx].name), false);
+                    self.release_constraint(idx);
+                    self.outputs[idx].position.0 -= 1;
+                    self.update_inputs_for_selection();
+                    self.layout_cache.clear();
                 }
             }
             Message::YInc => {
-                if let Some(idx) = self.selected_output_idx {
+                if let Some(idx) = self.selected_idx() {
+                    self.checkpoint(format!("Nudge {}", self.outputs[idx].name), false);
+                    self.release_constraint(idx);
                     self.outputs[idx].position.1 += 1;
                     self.update_inputs_for_selection();
                     self.layout_cache.clear();
                 }
             }
             Message::YDec => {
-                if let Some(idx) = self.selected_output_idx {
-                    if self.outputs[idx].position.1 > 0 {
-                        self.outputs[idx].position.1 -= 1;
-                        self.update_inputs_for_selection();
-                        self.layout_cache.clear();
-                    }
+                if let Some(idx) = self.selected_idx() {
+                    self.checkpoint(format!("Nudge {}", self.outputs[idx].name), false);
+                    self.release_constraint(idx);
+                    self.outputs[idx].position.1 -= 1;
+                    self.update_inputs_for_selection();
+                    self.layout_cache.clear();
                 }
             }
-            Message::ScaleChanged(val) => {
-                self.scale_input = val.clone();
-                if let (Some(idx), Ok(v)) = (self.selected_output_idx, f32::from_str(&val)) {
-                    if v > 0.1 {
-                        self.outputs[idx].scale = v;
-                        self.layout_cache.clear();
-                    }
+            Message::ScaleChanged(val) => self.set_scale(val, true),
+            Message::ScalePicked(val) => self.set_scale(val, false),
+            Message::ScaleInc | Message::ScaleDec => {
+                let steps = if matches!(message, Message::ScaleInc) {
+                    1
+                } else {
+                    -1
+                };
+                self.checkpoint(self.targets_label("Scale"), false);
+                for idx in self.targets() {
+                    self.outputs[idx].scale = scale::step(self.outputs[idx].scale, steps);
                 }
+                self.update_inputs_for_selection();
+                self.layout_cache.clear();
             }
-            Message::ScaleInc => {
-                if let Some(idx) = self.selected_output_idx {
-                    self.outputs[idx].scale += 0.05;
+            Message::UseRecommendedScale => {
+                if let Some(idx) = self.selected_idx()
+                    && let (Some(ppi), Some(cm)) = (
+                        self.outputs[idx].pixel_density(),
+                        self.outputs[idx].current_mode(),
+                    )
+                {
+                    let recommended = scale::recommended(cm.width, cm.height, ppi);
+                    self.checkpoint(format!("Scale {}", self.outputs[idx].name), false);
+                    self.outputs[idx].scale = recommended;
+                    self.update_inputs_for_selection();
+                    self.layout_cache.clear();
+                }
+            }
+            Message::MatchPhysicalSize => {
+                // Text is the same physical size wherever scale / ppi is equal,
+                // so every other output is scaled relative to the selected one
+                if let Some(idx) = self.selected_idx()
+                    && let Some(ref_ppi) = self.outputs[idx].pixel_density()
+                {
+                    self.checkpoint("Match physical size".to_string(), false);
+                    let ref_scale = self.outputs[idx].scale;
+                    let mut skipped = Vec::new();
+                    for (i, out) in self.outputs.iter_mut().enumerate() {
+                        if i == idx {
+                            continue;
+                        }
+                        match out.pixel_density() {
+                            Some(ppi) => out.scale = scale::snap(ref_scale * ppi / ref_ppi),
+                            None => skipped.push(out.name.clone()),
+                        }
+                    }
+                    self.status_message = if skipped.is_empty() {
+                        Some(format!(
+                            "Matched physical size to {}",
+                            self.outputs[idx].name
+                        ))
+                    } else {
+                        Some(format!(
+                            "Matched physical size to {}, skipped {} (unknown size)",
+                            self.outputs[idx].name,
+                            skipped.join(", ")
+                        ))
+                    };
                     self.update_inputs_for_selection();
                     self.layout_cache.clear();
                 }
             }
-            Message::ScaleDec => {}
             Message::EnabledToggled(val) => {
-                if let Some(idx) = self.selected_output_idx {
-                    self.outputs[idx].enabled = val;
+                let targets = self.targets();
+                let left_enabled = self
+                    .outputs
+                    .iter()
+                    .enumerate()
+                    .any(|(i, o)| o.enabled && !targets.contains(&i));
+                if !val && !left_enabled {
+                    self.status_message =
+                        Some("At least one output has to stay enabled".to_string());
+                } else {
+                    let verb = if val { "Enable" } else { "Disable" };
+                    self.checkpoint(self.targets_label(verb), false);
+                    for idx in targets {
+                        self.outputs[idx].enabled = val;
+                    }
+                    self.layout_cache.clear();
+                }
+            }
+            Message::OriginToggled(val) => {
+                if let Some(idx) = self.selected_idx() {
+                    let name = &self.outputs[idx].name;
+                    let label = if val {
+                        format!("Make {} the origin", name)
+                    } else {
+                        format!("Unpin {} from 0,0", name)
+                    };
+                    self.checkpoint(label, false);
+                    self.origin = val.then(|| self.outputs[idx].name.clone());
                     self.layout_cache.clear();
                 }
             }
-            Message::ResolutionSelected(res_idx) => {
-                if let Some(idx) = self.selected_output_idx {
-                    for m in &mut self.outputs[idx].modes {
-                        m.current = false;
+            Message::PlacementSideSelected(side) => {
+                if let Some(idx) = self.selected_idx() {
+                    self.set_constraint(idx, |c| c.side = side);
+                }
+            }
+            Message::PlacementAnchorSelected(anchor) => {
+                if let Some(idx) = self.selected_idx() {
+                    self.set_constraint(idx, |c| c.anchor = anchor);
+                }
+            }
+            Message::PlacementAlignSelected(choice) => {
+                if let Some(idx) = self.selected_idx() {
+                    self.set_constraint(idx, |c| c.align = choice.align);
+                }
+            }
+            Message::PlacementCleared => {
+                if let Some(idx) = self.selected_idx()
+                    && self.constraints.contains_key(&self.outputs[idx].name)
+                {
+                    self.checkpoint(format!("Free {}", self.outputs[idx].name), false);
+                    self.release_constraint(idx);
+                }
+            }
+            Message::ResolutionSelected(mode) => {
+                if let Some(idx) = self.selected_idx() {
+                    self.select_mode(idx, mode);
+                }
+            }
+            Message::ResolutionPicked(res) => {
+                if let Some(idx) = self.selected_idx()
+                    && let Some(mode) = self.mode_for_resolution(idx, res.width, res.height)
+                {
+                    self.select_mode(idx, mode);
+                }
+            }
+            Message::ResolutionStep(step) => {
+                if let Some(idx) = self.selected_idx() {
+                    let out = &self.outputs[idx];
+                    let resolutions = out.resolutions();
+                    let current = out.current_mode().and_then(|cm| {
+                        resolutions
+                            .iter()
+                            .position(|r| r.width == cm.width && r.height == cm.height)
+                    });
+                    // Resolutions run largest first, so stepping up walks back
+                    if let Some(res) = step_through(&resolutions, current, -step)
+                        && let Some(mode) = self.mode_for_resolution(idx, res.width, res.height)
+                    {
+                        self.select_mode(idx, mode);
                     }
-                    if res_idx < self.outputs[idx].modes.len() {
-                        self.outputs[idx].modes[res_idx].current = true;
+                }
+            }
+            Message::RefreshStep(step) => {
+                if let Some(idx) = self.selected_idx()
+                    && let Some(cm) = self.outputs[idx].current_mode()
+                {
+                    let out = &self.outputs[idx];
+                    let rates = out.refresh_rates(cm.width, cm.height);
+                    let current = rates
+                        .iter()
+                        .position(|m| out.modes[*m].refresh_rate == cm.refresh_rate);
+                    if let Some(&mode) = step_through(&rates, current, -step) {
+                        self.select_mode(idx, mode);
                     }
-                    self.layout_cache.clear();
                 }
             }
             Message::TransformSelected(trans) => {
-                if let Some(idx) = self.selected_output_idx {
-                    self.outputs[idx].transform = trans;
+                self.checkpoint(self.targets_label("Rotate"), false);
+                for idx in self.targets() {
+                    self.outputs[idx].transform = trans.clone();
+                }
+                self.layout_cache.clear();
+            }
+            Message::Arrange(arrangement) => {
+                self.checkpoint(arrangement.label().to_string(), false);
+                let enabled: Vec<usize> = (0..self.outputs.len())
+                    .filter(|i| self.outputs[*i].enabled)
+                    .collect();
+                let rects: Vec<_> = enabled
+                    .iter()
+                    .map(|i| layout::logical_rect(&self.outputs[*i]))
+                    .collect();
+                for (&i, pos) in enabled.iter().zip(layout::arrange(&rects, arrangement)) {
+                    self.outputs[i].position = pos;
+                }
+                let dropped = self.drop_broken_constraints(&enabled);
+                if dropped > 0 {
+                    self.status_message = Some(format!(
+                        "{}: dropped {} relative placements the new layout breaks",
+                        arrangement.label(),
+                        dropped
+                    ));
+                }
+                self.update_inputs_for_selection();
+                self.layout_cache.clear();
+            }
+            Message::Undo => {
+                if self.undo() {
+                    self.history_restored();
+                }
+            }
+            Message::Redo => {
+                if self.redo() {
+                    self.history_restored();
+                }
+            }
+            Message::HistoryShortcut(redo) => {
+                // Text fields don't claim these keys, so the subscription sees
+                // them even while one is being typed into
+                return operate(focus_held()).then(move |held| {
+                    if held {
+                        Task::none()
+                    } else if redo {
+                        Task::done(Message::Redo)
+                    } else {
+                        Task::done(Message::Undo)
+                    }
+                });
+            }
+            Message::HistoryJump(len) => {
+                self.step_history(|history, current| history.jump(len, current));
+                self.history_restored();
+            }
+            Message::FitView => {
+                self.fit_generation += 1;
+                self.layout_cache.clear();
+            }
+            Message::RulersToggled(show) => {
+                self.settings.show_rulers = show;
+                self.save_settings();
+            }
+            Message::PhysicalViewToggled(on) => {
+                self.physical_view = on;
+                self.fit_generation += 1;
+                self.layout_cache.clear();
+            }
+            Message::BezelChanged(val) => {
+                self.bezel_input = val.clone();
+                if let (Some(idx), Ok(mm)) = (self.selected_idx(), f32::from_str(&val))
+                    && mm >= 0.0
+                {
+                    let name = self.outputs[idx].name.clone();
+                    self.settings.bezels_mm.insert(name, mm);
                     self.layout_cache.clear();
+                    self.save_settings();
                 }
             }
+            Message::PanelGapChanged(val) => {
+                self.panel_gap_input = val.clone();
+                if let Ok(mm) = f32::from_str(&val)
+                    && mm >= 0.0
+                {
+                    self.settings.panel_gap_mm = mm;
+                    self.save_settings();
+                }
+            }
+            Message::CompensateBezels => {
+                self.checkpoint("Compensate bezels".to_string(), false);
+                let enabled: Vec<usize> = (0..self.outputs.len())
+                    .filter(|i| self.outputs[*i].enabled)
+                    .collect();
+                let rects: Vec<_> = enabled
+                    .iter()
+                    .map(|i| layout::logical_rect(&self.outputs[*i]))
+                    .collect();
+                let positions = layout::spread(&rects, |a, b| self.seam_px(enabled[a], enabled[b]));
+                let mut moved = 0;
+                for (&i, pos) in enabled.iter().zip(positions) {
+                    if self.outputs[i].position != pos {
+                        self.outputs[i].position = pos;
+                        moved += 1;
+                    }
+                }
+                // A relative placement would pull its output back against the
+                // anchor and close the gap again
+                let dropped = self.drop_broken_constraints(&enabled);
+                let mut status = if moved == 0 {
+                    "Bezels are already compensated".to_string()
+                } else {
+                    format!(
+                        "Moved {} outputs to make up for bezels. The pointer can't cross the seams this leaves",
+                        moved
+                    )
+                };
+                if dropped > 0 {
+                    status.push_str(&format!(
+                        ", and dropped {} relative placements that would have closed the gaps",
+                        dropped
+                    ));
+                }
+                self.status_message = Some(status);
+                self.update_inputs_for_selection();
+                self.layout_cache.clear();
+            }
+            Message::GridToggled(show) => {
+                self.settings.show_grid = show;
+                self.layout_cache.clear();
+                self.save_settings();
+            }
             Message::ApplyClicked => {
+                if self.busy.is_some() {
+                    return Task::none();
+                }
                 self.normalize_positions();
-                match wlr_randr_apply(&self.outputs) {
-                    Ok(()) => self.status_message = Some("Applied successfully!".to_string()),
-                    Err(e) => self.status_message = Some(format!("Apply error: {}", e)),
+                let warnings = match self.check_layout() {
+                    Ok(warnings) => warnings,
+                    Err(e) => {
+                        self.status_message = Some(format!("Apply blocked: {}", e));
+                        return Task::none();
+                    }
+                };
+                let cancel = Arc::new(AtomicBool::new(false));
+                self.busy = Some(Busy {
+                    label: "Applying",
+                    started: Instant::now(),
+                    cancel: Some(cancel.clone()),
+                    warnings,
+                });
+                self.apply_report.clear();
+                let outputs = self.outputs.clone();
+                return Task::perform(
+                    off_thread(move || {
+                        // Diffed against a fresh read, in case something else
+                        // changed the outputs since the last poll
+                        let before = wlr_randr_get_outputs()?;
+                        let touched = wlr_randr_apply(&outputs, &before, &cancel)?;
+                        let live = wlr_randr_get_outputs()?;
+                        let mismatches = compare_applied(&outputs, &live);
+                        Ok(Applied {
+                            touched,
+                            live,
+                            mismatches,
+                        })
+                    }),
+                    Message::ApplyFinished,
+                );
+            }
+            Message::ApplyFinished(result) => {
+                let Some(busy) = self.busy.take() else {
+                    return Task::none();
+                };
+                let failed = result.is_err();
+                self.status_message = Some(match result {
+                    Ok(applied) => {
+                        let mut live = applied.live;
+                        // Settled like every other read, or merging would take
+                        // disabled outputs for edited ones
+                        for out in &mut live {
+                            out.settle_mode();
+                        }
+                        self.live = live;
+                        self.apply_report = applied.mismatches;
+                        match (self.apply_report.is_empty(), busy.warnings) {
+                            (true, None) if applied.touched.is_empty() => {
+                                "Nothing to apply, the outputs already match".to_string()
+                            }
+                            (true, Some(w)) => format!("Applied with warnings: {}", w),
+                            (true, None) => {
+                                format!("Applied changes to {}", applied.touched.join(", "))
+                            }
+                            (false, Some(w)) => format!(
+                                "Applied, but the compositor changed some settings. Warnings: {}",
+                                w
+                            ),
+                            (false, None) => {
+                                "Applied, but the compositor changed some settings".to_string()
+                            }
+                        }
+                    }
+                    Err(_) if busy.cancelled() => "Apply cancelled".to_string(),
+                    Err(e) => failure("Apply", &e),
+                });
+                if failed {
+                    // Part of it may have gone through, so read back what the
+                    // compositor has now rather than trust the last poll
+                    return Task::perform(
+                        off_thread(wlr_randr_get_outputs),
+                        Message::OutputsScanned,
+                    );
                 }
             }
             Message::SaveClicked => {
+                if self.busy.is_some() {
+                    return Task::none();
+                }
                 self.normalize_positions();
-                match wlr_randr_save(&self.outputs, &self.settings) {
-                    Ok(()) => {
-                        self.status_message =
-                            Some(format!("Saved to {}", self.settings.monitors_conf_path))
+                let warnings = match self.check_layout() {
+                    Ok(warnings) => warnings,
+                    Err(e) => {
+                        self.status_message = Some(format!("Save blocked: {}", e));
+                        return Task::none();
                     }
-                    Err(e) => self.status_message = Some(format!("Save error: {}", e)),
+                };
+                self.busy = Some(Busy {
+                    label: "Saving",
+                    started: Instant::now(),
+                    cancel: None,
+                    warnings,
+                });
+                let outputs = self.outputs.clone();
+                let constraints = self.constraints.clone();
+                let settings = self.settings.clone();
+                return Task::perform(
+                    off_thread(move || wlr_randr_save(&outputs, &constraints, &settings)),
+                    Message::SaveFinished,
+                );
+            }
+            Message::SaveFinished(result) => {
+                let Some(busy) = self.busy.take() else {
+                    return Task::none();
+                };
+                self.status_message = Some(match result {
+                    Ok(problems) => {
+                        let warnings: Vec<String> = busy
+                            .warnings
+                            .into_iter()
+                            .chain(problems.iter().map(Error::to_string))
+                            .collect();
+                        if warnings.is_empty() {
+                            format!("Saved to {}", self.settings.monitors_conf_path)
+                        } else {
+                            format!(
+                                "Saved to {} with warnings: {}",
+                                self.settings.monitors_conf_path,
+                                warnings.join("; ")
+                            )
+                        }
+                    }
+                    Err(e) => failure("Save", &e),
+                });
+            }
+            Message::CancelClicked => {
+                if let Some(cancel) = self.busy.as_ref().and_then(|b| b.cancel.as_ref()) {
+                    cancel.store(true, Ordering::Relaxed);
                 }
             }
+            Message::BusyTick => {}
             Message::RestoreDefaultClicked => {
-                match wlr_randr_restore_default(&self.settings) {
-                    Ok(()) => self.status_message = Some("Restored to default config!".to_string()),
-                    Err(e) => self.status_message = Some(format!("Restore error: {}", e)),
+                if self.busy.is_some() {
+                    return Task::none();
+                }
+                self.busy = Some(Busy {
+                    label: "Restoring",
+                    started: Instant::now(),
+                    cancel: None,
+                    warnings: None,
+                });
+                let settings = self.settings.clone();
+                return Task::perform(
+                    off_thread(move || wlr_randr_restore_default(&settings)),
+                    Message::RestoreFinished,
+                );
+            }
+            Message::RestoreFinished(result) => {
+                if self.busy.take().is_none() {
+                    return Task::none();
                 }
+                self.status_message = Some(match result {
+                    Ok(()) => "Restored to default config!".to_string(),
+                    Err(e) => failure("Restore", &e),
+                });
             }
+            Message::RescanClicked => {
+                return Task::perform(off_thread(wlr_randr_get_outputs), Message::RescanFinished);
+            }
+            Message::RescanFinished(scanned) => self.rescan(scanned, true),
+            Message::OutputsScanned(scanned) => self.rescan(scanned, false),
         }
+        self.solve_constraints();
+        self.prune_apply_report();
         Task::none()
     }
 
     pub fn view(&self) -> Element<'_, Message> {
+        let issues = self.validate_layout();
         let canvas = Canvas::new(LayoutCanvas {
             outputs: self.outputs.clone(),
-            selected_idx: self.selected_output_idx,
+            selected_idx: self.selected_idx(),
+            selection: self.targets(),
+            flagged: issues
+                .iter()
+                .flat_map(Issue::outputs)
+                .chain(
+                    self.apply_report
+                        .iter()
+                        .filter_map(|m| self.outputs.iter().position(|o| o.name == m.output)),
+                )
+                .collect(),
+            origin: self.origin_idx(),
+            snap: self.settings.snap_config(),
+            show_rulers: self.settings.show_rulers,
+            show_grid: self.settings.show_grid,
+            physical: self.physical_view.then(|| self.mm_per_px()),
+            bezels: self
+                .outputs
+                .iter()
+                .map(|o| self.settings.bezels_mm.get(&o.name).copied().unwrap_or(0.0))
+                .collect(),
+            fit_generation: self.fit_generation,
             cache: &self.layout_cache,
         })
         .width(Length::Fill)
@@ -239,20 +1589,40 @@ impl MangoDisplay {
 
         let mut tabs_row = row![].spacing(0);
         for (i, out) in self.outputs.iter().enumerate() {
-            let is_selected = Some(i) == self.selected_output_idx;
+            let is_selected = Some(i) == self.selected_idx();
+            let colour = output_colour(i);
             let current_btn = button(text(&out.name).align_x(alignment::Horizontal::Center))
                 .width(Length::Fixed(80.0))
-                .style(if is_selected {
-                    button::primary
-                } else {
-                    button::secondary
+                .style(move |theme, status| {
+                    let mut style = if is_selected {
+                        button::primary(theme, status)
+                    } else {
+                        button::secondary(theme, status)
+                    };
+                    if is_selected {
+                        style.background = Some(colour.into());
+                        style.text_color = Color::BLACK;
+                    }
+                    style.border = style.border.color(colour).width(2);
+                    style
                 })
-                .on_press(Message::MonitorClicked(i));
+                .on_press(Message::MonitorClicked(out.id()));
             tabs_row = tabs_row.push(current_btn);
         }
         sidebar = sidebar.push(container(tabs_row).center_x(Length::Fill));
 
-        if let Some(idx) = self.selected_output_idx {
+        if self.selection.len() > 1 {
+            sidebar = sidebar.push(
+                text(format!(
+                    "{} outputs selected: scale, transform and enabled apply to all",
+                    self.selection.len()
+                ))
+                .size(13)
+                .color(Color::from_rgb8(150, 150, 150)),
+            );
+        }
+
+        if let Some(idx) = self.selected_idx() {
             let out = &self.outputs[idx];
 
             if self.outputs.len() > 1 {
@@ -260,7 +1630,10 @@ impl MangoDisplay {
                     row![
                         Space::new().width(100.0),
                         checkbox(out.enabled).on_toggle(Message::EnabledToggled),
-                        text("Enabled")
+                        text("Enabled"),
+                        checkbox(self.origin.as_ref() == Some(&out.name))
+                            .on_toggle(Message::OriginToggled),
+                        text("Origin (pinned to 0,0)"),
                     ]
                     .spacing(10),
                 );
@@ -270,16 +1643,15 @@ impl MangoDisplay {
 
             let row_desc = row![
                 container(text("Description").size(14)).width(label_width),
-                text(&out.description).size(14)
+                text(out.description.as_deref().unwrap_or("None")).size(14)
             ]
             .spacing(10)
             .align_y(alignment::Vertical::Center);
             sidebar = sidebar.push(row_desc);
 
-            let phys_size_text = if out.physical_size.is_empty() {
-                "Unknown".to_string()
-            } else {
-                out.physical_size.clone()
+            let phys_size_text = match out.physical_size {
+                Some((w, h)) => format!("{} x {} mm", w, h),
+                None => "Unknown".to_string(),
             };
             let row_phys = row![
                 container(text("Physical Size").size(14)).width(label_width),
@@ -289,10 +1661,13 @@ impl MangoDisplay {
             .align_y(alignment::Vertical::Center);
             sidebar = sidebar.push(row_phys);
 
+            let cm = out.current_mode();
+
             let row_scale = row![
                 container(text("DPI Scale").size(14)).width(label_width),
                 text_input("", &self.scale_input)
                     .on_input(Message::ScaleChanged)
+                    .on_submit(Message::EditFinished)
                     .width(Length::Fixed(60.0)),
                 button("-").on_press(Message::ScaleDec),
                 button("+").on_press(Message::ScaleInc),
@@ -301,15 +1676,74 @@ impl MangoDisplay {
             .align_y(alignment::Vertical::Center);
             sidebar = sidebar.push(row_scale);
 
+            if let Some(cm) = cm {
+                let crisp: Vec<String> = scale::crisp_scales(cm.width, cm.height)
+                    .into_iter()
+                    .map(scale::format)
+                    .collect();
+                let selected_crisp = crisp
+                    .iter()
+                    .find(|s| **s == scale::format(out.scale))
+                    .cloned();
+                let row_crisp = row![
+                    container(text("Crisp Scales").size(14)).width(label_width),
+                    pick_list(crisp, selected_crisp, Message::ScalePicked)
+                        .width(Length::Fixed(100.0)),
+                ]
+                .spacing(5)
+                .align_y(alignment::Vertical::Center);
+                sidebar = sidebar.push(row_crisp);
+
+                if !scale::is_crisp(cm.width, cm.height, out.scale) {
+                    let (lw, lh) = scale::logical_size(cm.width, cm.height, out.scale);
+                    sidebar = sidebar.push(
+                        text(format!(
+                            "Logical size {:.2}x{:.2} is not a whole number of pixels, expect blurry output",
+                            lw, lh
+                        ))
+                        .size(13),
+                    );
+                }
+
+                if let Some(ppi) = out.pixel_density() {
+                    let recommended = scale::recommended(cm.width, cm.height, ppi);
+                    let row_density = row![
+                        container(text("Density").size(14)).width(label_width),
+                        text(format!(
+                            "{:.0} PPI, suggests {}",
+                            ppi,
+                            scale::format(recommended)
+                        ))
+                        .size(14),
+                        button("Use").on_press(Message::UseRecommendedScale),
+                    ]
+                    .spacing(5)
+                    .align_y(alignment::Vertical::Center);
+                    sidebar = sidebar.push(row_density);
+
+                    if self.outputs.len() > 1 {
+                        sidebar = sidebar.push(
+                            row![
+                                Space::new().width(label_width),
+                                button("Match Physical Size").on_press(Message::MatchPhysicalSize),
+                            ]
+                            .spacing(5),
+                        );
+                    }
+                }
+            }
+
             let row_pos = row![
                 container(text("Position").size(14)).width(label_width),
                 text_input("", &self.x_input)
                     .on_input(Message::XChanged)
+                    .on_submit(Message::EditFinished)
                     .width(Length::Fixed(60.0)),
                 button("-").on_press(Message::XDec),
                 button("+").on_press(Message::XInc),
                 text_input("", &self.y_input)
                     .on_input(Message::YChanged)
+                    .on_submit(Message::EditFinished)
                     .width(Length::Fixed(60.0)),
                 button("-").on_press(Message::YDec),
                 button("+").on_press(Message::YInc),
@@ -318,76 +1752,123 @@ impl MangoDisplay {
             .align_y(alignment::Vertical::Center);
             sidebar = sidebar.push(row_pos);
 
-            let cm = out
-                .modes
-                .iter()
-                .find(|m| m.current)
-                .cloned()
-                .unwrap_or(OutputMode {
-                    width: 1920,
-                    height: 1080,
-                    refresh_rate: 60.0,
-                    current: true,
-                    preferred: false,
-                });
+            if self.outputs.len() > 1 {
+                let constraint = self.constraints.get(&out.name);
+                let mut row_place = row![
+                    container(text("Placement").size(14)).width(label_width),
+                    pick_list(
+                        Side::ALL,
+                        constraint.map(|c| c.side),
+                        Message::PlacementSideSelected
+                    )
+                    .placeholder("Free")
+                    .width(Length::Fixed(90.0)),
+                ]
+                .spacing(5)
+                .align_y(alignment::Vertical::Center);
+                if let Some(c) = constraint {
+                    let aligns: Vec<AlignChoice> = Align::ALL
+                        .into_iter()
+                        .map(|align| AlignChoice {
+                            align,
+                            side: c.side,
+                        })
+                        .collect();
+                    row_place = row_place
+                        .push(
+                            pick_list(
+                                self.anchor_candidates(idx),
+                                Some(c.anchor.clone()),
+                                Message::PlacementAnchorSelected,
+                            )
+                            .width(Length::Fixed(80.0)),
+                        )
+                        .push(button("x").on_press(Message::PlacementCleared));
+                    sidebar = sidebar.push(row_place).push(
+                        row![
+                            Space::new().width(label_width),
+                            pick_list(
+                                aligns,
+                                Some(AlignChoice {
+                                    align: c.align,
+                                    side: c.side,
+                                }),
+                                Message::PlacementAlignSelected,
+                            )
+                            .width(Length::Fixed(140.0)),
+                        ]
+                        .spacing(5),
+                    );
+                } else {
+                    sidebar = sidebar.push(row_place);
+                }
+            }
 
-            let row_size = row![
-                container(text("Size").size(14)).width(label_width),
-                text_input("", &cm.width.to_string()).width(Length::Fixed(60.0)),
-                button("-"),
-                button("+"),
-                text("x"),
-                text_input("", &cm.height.to_string()).width(Length::Fixed(60.0)),
-                button("-"),
-                button("+"),
-                button("!"),
+            let resolutions = out.resolutions();
+            let current_res = cm.and_then(|cm| {
+                resolutions
+                    .iter()
+                    .find(|r| r.width == cm.width && r.height == cm.height)
+                    .cloned()
+            });
+            let row_res = row![
+                container(text("Resolution").size(14)).width(label_width),
+                pick_list(resolutions, current_res, Message::ResolutionPicked)
+                    .placeholder("No mode")
+                    .width(Length::Fixed(200.0)),
+                button("-").on_press(Message::ResolutionStep(-1)),
+                button("+").on_press(Message::ResolutionStep(1)),
             ]
             .spacing(5)
             .align_y(alignment::Vertical::Center);
-            sidebar = sidebar.push(row_size);
-
-            let mut current_res_idx = 0;
-            let mut resolutions = Vec::new();
-            for (i, m) in out.modes.iter().enumerate() {
-                resolutions.push(format!("{:.3}", m.refresh_rate));
-                if m.current {
-                    current_res_idx = i;
-                }
-            }
-            let res_options = resolutions.clone();
-            let selected_res = if current_res_idx < resolutions.len() {
-                Some(resolutions[current_res_idx].clone())
-            } else {
-                None
-            };
-            let pick_rr = pick_list(res_options, selected_res, move |selected: String| {
-                let i = resolutions.iter().position(|r| *r == selected).unwrap_or(0);
-                Message::ResolutionSelected(i)
-            })
-            .width(Length::Fixed(100.0));
-
+            sidebar = sidebar.push(row_res);
+
+            let rates: Vec<RefreshChoice> = cm
+                .map(|cm| out.refresh_rates(cm.width, cm.height))
+                .unwrap_or_default()
+                .into_iter()
+                .map(|mode| RefreshChoice {
+                    mode,
+                    rate: out.modes[mode].refresh_rate,
+                })
+                .collect();
+            let current_rate =
+                cm.and_then(|cm| rates.iter().copied().find(|r| r.rate == cm.refresh_rate));
             let row_rr = row![
                 container(text("Refresh Rate").size(14)).width(label_width),
-                pick_rr,
-                button("-"),
-                button("+"),
+                pick_list(rates, current_rate, |r| Message::ResolutionSelected(r.mode))
+                    .width(Length::Fixed(100.0)),
+                button("-").on_press(Message::RefreshStep(-1)),
+                button("+").on_press(Message::RefreshStep(1)),
                 text("Hz").size(14)
             ]
             .spacing(5)
             .align_y(alignment::Vertical::Center);
             sidebar = sidebar.push(row_rr);
 
-            let transforms = vec![
-                "normal".to_string(),
-                "90".to_string(),
-                "180".to_string(),
-                "270".to_string(),
-                "flipped".to_string(),
-                "flipped-90".to_string(),
-                "flipped-180".to_string(),
-                "flipped-270".to_string(),
-            ];
-            let pick_trans = pick_list(transforms.clone(), Some(out.transform.clone()), |t| {
+            let can_use_preferred = out.modes.iter().any(|m| m.preferred && !m.current);
+            sidebar = sidebar.push(
+                row![
+                    Space::new().width(label_width),
+                    button(text("Use Preferred Mode").size(13)).on_press_maybe(
+                        can_use_preferred.then_some(Message::UsePreferredMode(out.id()))
+                    ),
+                ]
+                .spacing(5),
+            );
+            if out.modes.is_empty() {
+                sidebar = sidebar.push(
+                    text(format!(
+                        "{} reports no modes, so it can't be applied or saved",
+                        out.name
+                    ))
+                    .size(13)
+                    .color(Color::from_rgb8(210, 70, 70)),
+                );
+            }
+
+            let transforms: Vec<String> = TRANSFORMS.iter().map(|t| t.to_string()).collect();
+            let pick_trans = pick_list(transforms, Some(out.transform.clone()), |t| {
                 Message::TransformSelected(t)
             })
             .width(Length::Fixed(200.0));
@@ -401,14 +1882,169 @@ impl MangoDisplay {
             sidebar = sidebar.push(row_trans);
         }
 
+        if self.outputs.len() > 1 {
+            let arrange_button = |arrangement: Arrangement| {
+                button(text(arrangement.label()).size(13)).on_press(Message::Arrange(arrangement))
+            };
+            sidebar = sidebar.push(
+                column![
+                    text("Arrange").size(14),
+                    row![
+                        arrange_button(Arrangement::LeftToRight),
+                        arrange_button(Arrangement::TopToBottom),
+                        arrange_button(Arrangement::Pack),
+                    ]
+                    .spacing(5),
+                    row![
+                        arrange_button(Arrangement::AlignTop),
+                        arrange_button(Arrangement::AlignMiddle),
+                        arrange_button(Arrangement::AlignBottom),
+                    ]
+                    .spacing(5),
+                ]
+                .spacing(5),
+            );
+        }
+
+        let any_not_preferred = self
+            .outputs
+            .iter()
+            .any(|o| o.modes.iter().any(|m| m.preferred && !m.current));
+        sidebar = sidebar.push(
+            row![
+                text("Modes").size(14).width(Length::Fill),
+                button(text("Use Preferred Modes").size(13))
+                    .on_press_maybe(any_not_preferred.then_some(Message::UsePreferredModes)),
+            ]
+            .align_y(alignment::Vertical::Center),
+        );
+
+        sidebar = sidebar.push(
+            column![
+                row![
+                    text("View").size(14).width(Length::Fill),
+                    button(text("Fit to Layout").size(13)).on_press(Message::FitView),
+                ]
+                .align_y(alignment::Vertical::Center),
+                row![
+                    checkbox(self.settings.show_rulers).on_toggle(Message::RulersToggled),
+                    text("Rulers").size(13),
+                    checkbox(self.settings.show_grid).on_toggle(Message::GridToggled),
+                    text("Grid").size(13),
+                    checkbox(self.physical_view).on_toggle(Message::PhysicalViewToggled),
+                    text("Physical Size").size(13),
+                ]
+                .spacing(8)
+                .align_y(alignment::Vertical::Center),
+            ]
+            .spacing(5),
+        );
+
+        if self.physical_view {
+            let mut bezels = row![].spacing(5).align_y(alignment::Vertical::Center);
+            if let Some(idx) = self.selected_idx() {
+                bezels = bezels.push(text(format!("{} bezel", self.outputs[idx].name)).size(13));
+                bezels = bezels.push(
+                    text_input("mm", &self.bezel_input)
+                        .on_input(Message::BezelChanged)
+                        .width(Length::Fixed(50.0)),
+                );
+            }
+            sidebar = sidebar.push(
+                bezels
+                    .push(text("Gap").size(13))
+                    .push(
+                        text_input("mm", &self.panel_gap_input)
+                            .on_input(Message::PanelGapChanged)
+                            .width(Length::Fixed(50.0)),
+                    )
+                    .push(Space::new().width(Length::Fill))
+                    .push(
+                        button(text("Compensate Bezels").size(13))
+                            .on_press(Message::CompensateBezels),
+                    ),
+            );
+        }
+
+        let mut history = column![
+            row![
+                text("History").size(14).width(Length::Fill),
+                button(text("Undo").size(13))
+                    .on_press_maybe(self.history.can_undo().then_some(Message::Undo)),
+                button(text("Redo").size(13))
+                    .on_press_maybe(self.history.can_redo().then_some(Message::Redo)),
+            ]
+            .spacing(5)
+            .align_y(alignment::Vertical::Center)
+        ]
+        .spacing(2);
+        for (i, label) in self.history.undo_labels().enumerate() {
+            history = history.push(
+                button(text(label).size(13))
+                    .style(button::text)
+                    .padding(2)
+                    .on_press(Message::HistoryJump(i)),
+            );
+        }
+        for label in self.history.redo_labels() {
+            history = history.push(
+                container(text(label).size(13).color(Color::from_rgb8(110, 110, 110))).padding(2),
+            );
+        }
+        sidebar = sidebar.push(history);
+
+        if !issues.is_empty() {
+            let mut issue_list = column![text("Layout Problems").size(14)].spacing(4);
+            for issue in &issues {
+                issue_list = issue_list.push(text(self.describe_issue(issue)).size(13));
+            }
+            sidebar = sidebar.push(issue_list);
+        }
+
+        if !self.apply_report.is_empty() {
+            let mut report = column![text("Not Applied as Requested").size(14)].spacing(4);
+            for mismatch in &self.apply_report {
+                report = report.push(text(mismatch.to_string()).size(13));
+            }
+            sidebar = sidebar.push(report);
+        }
+
         if let Some(ref msg) = self.status_message {
             sidebar = sidebar.push(text(msg).size(13));
         }
 
+        if let Some(busy) = &self.busy {
+            let elapsed = busy.started.elapsed();
+            let frame = SPINNER
+                [(elapsed.as_millis() / SPINNER_INTERVAL.as_millis()) as usize % SPINNER.len()];
+            let mut progress = row![
+                text(format!(
+                    "{} {}... {}s",
+                    frame,
+                    busy.label,
+                    elapsed.as_secs()
+                ))
+                .size(13)
+            ]
+            .spacing(10)
+            .align_y(alignment::Vertical::Center);
+            if busy.cancel.is_some() {
+                progress = progress.push(
+                    button(text("Cancel").size(13))
+                        .on_press_maybe((!busy.cancelled()).then_some(Message::CancelClicked)),
+                );
+            }
+            sidebar = sidebar.push(progress);
+        }
+
+        // Nothing else touches the outputs or the config while one runs
+        let idle = self.busy.is_none();
         let actions = row![
-            button("Apply").on_press(Message::ApplyClicked),
-            button("Save").on_press(Message::SaveClicked),
-            button("Restore Default").on_press(Message::RestoreDefaultClicked),
+            button("Apply").on_press_maybe(idle.then_some(Message::ApplyClicked)),
+            button("Save").on_press_maybe(idle.then_some(Message::SaveClicked)),
+            button("Restore Default")
+                .on_press_maybe(idle.then_some(Message::RestoreDefaultClicked)),
+            button("Rescan").on_press_maybe(idle.then_some(Message::RescanClicked)),
         ]
         .spacing(10);
 
@@ -429,111 +2065,713 @@ impl MangoDisplay {
     }
 }
 
+struct Drag {
+    /// Output under the cursor, the one that snaps to its neighbours
+    anchor: OutputId,
+    start_cursor: Point,
+    /// Everything moving along with the anchor and where it started
+    members: Vec<(OutputId, (i32, i32))>,
+}
+
+/// A right-click menu open on one output.
+struct Menu {
+    id: OutputId,
+    at: Point,
+    hovered: Option<usize>,
+}
+
 #[derive(Default)]
 pub struct CanvasState {
-    dragging: Option<(usize, Point, (i32, i32))>,
+    dragging: Option<Drag>,
+    /// Rubber band selection, from where the drag started to the cursor
+    band: Option<(Point, Point)>,
+    /// Disabled output being dragged out of the tray, and the cursor
+    unparking: Option<(OutputId, Point)>,
+    menu: Option<Menu>,
     hovered: Option<usize>,
+    // Keys only drive the canvas after it was clicked, so typing into the
+    // sidebar doesn't nudge monitors around
+    focused: bool,
+    view: Option<View>,
+    fit_generation: u64,
+    panning: Option<(Point, View)>,
+    modifiers: keyboard::Modifiers,
+    /// Snap lines to draw while an output is being dragged
+    guides: Vec<Guide>,
+}
+
+// Room left around the layout when fitting it into the canvas
+const FIT_MARGIN: f32 = 1.25;
+const MIN_ZOOM: f32 = 0.005;
+const MAX_ZOOM: f32 = 2.0;
+// Anything smaller than this is treated as a click rather than a rubber band
+const BAND_MIN_SIZE: f32 = 4.0;
+const RULER_SIZE: f32 = 20.0;
+// Strip along the bottom of the canvas holding disabled outputs
+const TRAY_HEIGHT: f32 = 80.0;
+const CHIP_WIDTH: f32 = 120.0;
+const CHIP_SPACING: f32 = 10.0;
+const MENU_WIDTH: f32 = 200.0;
+const MENU_ITEM_HEIGHT: f32 = 26.0;
+// Grid lines and ruler labels are kept at least this far apart on screen
+const GRID_MIN_SPACING: f32 = 60.0;
+
+/// The smallest 1-2-5 step in logical pixels that stays readable at `scale`,
+/// or `None` when no step would, as for a scale that isn't positive.
+fn grid_step(scale: f32) -> Option<i32> {
+    if !(scale > 0.0 && scale.is_finite()) {
+        return None;
+    }
+    let mut magnitude: i32 = 1;
+    loop {
+        for m in [1, 2, 5] {
+            let step = magnitude.checked_mul(m)?;
+            if step as f32 * scale >= GRID_MIN_SPACING {
+                return Some(step);
+            }
+        }
+        magnitude = magnitude.checked_mul(10)?;
+    }
+}
+
+/// Maps logical layout coordinates onto the canvas.
+#[derive(Debug, Clone, Copy)]
+struct View {
+    scale: f32,
+    offset_x: f32,
+    offset_y: f32,
+}
+
+impl View {
+    fn to_screen(self, x: f32, y: f32) -> Point {
+        Point::new(
+            x * self.scale + self.offset_x,
+            y * self.scale + self.offset_y,
+        )
+    }
+
+    fn to_logical(self, p: Point) -> (f32, f32) {
+        (
+            (p.x - self.offset_x) / self.scale,
+            (p.y - self.offset_y) / self.scale,
+        )
+    }
+
+    /// Zooms by `factor` while keeping the logical point under `anchor` fixed.
+    fn zoomed(self, factor: f32, anchor: Point) -> View {
+        let (lx, ly) = self.to_logical(anchor);
+        let scale = (self.scale * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        View {
+            scale,
+            offset_x: anchor.x - lx * scale,
+            offset_y: anchor.y - ly * scale,
+        }
+    }
+}
+
+// One colour per output, shared by the canvas and the tab row
+const PALETTE: [Color; 6] = [
+    Color::from_rgb8(86, 156, 214),
+    Color::from_rgb8(214, 157, 86),
+    Color::from_rgb8(120, 190, 120),
+    Color::from_rgb8(190, 120, 200),
+    Color::from_rgb8(90, 190, 190),
+    Color::from_rgb8(210, 200, 100),
+];
+
+fn output_colour(idx: usize) -> Color {
+    PALETTE[idx % PALETTE.len()]
+}
+
+fn mix(a: Color, b: Color, t: f32) -> Color {
+    Color::from_rgb(
+        a.r + (b.r - a.r) * t,
+        a.g + (b.g - a.g) * t,
+        a.b + (b.b - a.b) * t,
+    )
+}
+
+/// Mode, scale, logical size and other notable lines shown under an output's
+/// name.
+fn output_details(out: &Output) -> Vec<String> {
+    let mut lines = Vec::new();
+    match out.current_mode() {
+        Some(mode) => lines.push(match mode.refresh_rate {
+            Some(rate) => format!("{}x{} @ {:.2} Hz", mode.width, mode.height, rate),
+            None => format!("{}x{}", mode.width, mode.height),
+        }),
+        None => lines.push("No mode".to_string()),
+    }
+    let r = layout::logical_rect(out);
+    lines.push(format!(
+        "Scale {}, {}x{} logical",
+        scale::format(out.scale),
+        r.w,
+        r.h
+    ));
+    if out.transform != "normal" {
+        lines.push(format!("Transform {}", out.transform));
+    }
+    if out.adaptive_sync == Some(true) {
+        lines.push("Adaptive Sync".to_string());
+    }
+    lines
+}
+
+/// Marks the edge the top of the panel sits on once the transform is applied,
+/// with a notch at the panel's top-left corner so flips are visible too.
+fn draw_top_edge(frame: &mut canvas::Frame, rect: Rectangle, transform: &str, color: Color) {
+    let (turns, flipped) = layout::parse_transform(transform);
+    let thickness = (rect.width.min(rect.height) * 0.04).clamp(3.0, 10.0);
+    let (l, t, r, b) = (rect.x, rect.y, rect.x + rect.width, rect.y + rect.height);
+    // Corners clockwise from top-left, so a quarter turn shifts by one
+    let corners = [
+        Point::new(l, t),
+        Point::new(r, t),
+        Point::new(r, b),
+        Point::new(l, b),
+    ];
+    let start = corners[turns as usize];
+    let end = corners[(turns as usize + 1) % 4];
+    frame.stroke(
+        &Path::line(start, end),
+        canvas::Stroke::default()
+            .with_color(color)
+            .with_width(thickness),
+    );
+    let notch = if flipped { end } else { start };
+    frame.fill(&Path::circle(notch, thickness * 1.5), color);
+}
+
+/// Background lines every grid step, with the 0,0 axes picked out.
+fn draw_grid(frame: &mut canvas::Frame, view: View, size: Size) {
+    let Some(step) = grid_step(view.scale) else {
+        return;
+    };
+    let (left, top) = view.to_logical(Point::ORIGIN);
+    let (right, bottom) = view.to_logical(Point::new(size.width, size.height));
+    let line = |at: i32| {
+        canvas::Stroke::default()
+            .with_color(if at == 0 {
+                Color::from_rgb8(50, 50, 50)
+            } else {
+                Color::from_rgb8(28, 28, 28)
+            })
+            .with_width(1.0)
+    };
+
+    let mut x = (left / step as f32).floor() as i32 * step;
+    while (x as f32) <= right {
+        let sx = view.to_screen(x as f32, 0.0).x;
+        frame.stroke(
+            &Path::line(Point::new(sx, 0.0), Point::new(sx, size.height)),
+            line(x),
+        );
+        x += step;
+    }
+    let mut y = (top / step as f32).floor() as i32 * step;
+    while (y as f32) <= bottom {
+        let sy = view.to_screen(0.0, y as f32).y;
+        frame.stroke(
+            &Path::line(Point::new(0.0, sy), Point::new(size.width, sy)),
+            line(y),
+        );
+        y += step;
+    }
+}
+
+/// Rulers along the top and left edges in canvas units, plus the cursor's
+/// position in the bottom-left corner.
+fn draw_rulers(
+    frame: &mut canvas::Frame,
+    view: View,
+    size: Size,
+    cursor: Option<Point>,
+    unit: &str,
+) {
+    let background = Color::from_rgb8(25, 25, 25);
+    let ink = Color::from_rgb8(140, 140, 140);
+    let tick = canvas::Stroke::default().with_color(ink).with_width(1.0);
+    frame.fill_rectangle(Point::ORIGIN, Size::new(size.width, RULER_SIZE), background);
+    frame.fill_rectangle(
+        Point::ORIGIN,
+        Size::new(RULER_SIZE, size.height),
+        background,
+    );
+
+    if let Some(step) = grid_step(view.scale) {
+        let minor = (step / 5).max(1);
+        let (left, top) = view.to_logical(Point::ORIGIN);
+        let (right, bottom) = view.to_logical(Point::new(size.width, size.height));
+
+        let mut x = (left / minor as f32).floor() as i32 * minor;
+        while (x as f32) <= right {
+            let sx = view.to_screen(x as f32, 0.0).x;
+            let major = x % step == 0;
+            let length = if major { RULER_SIZE } else { RULER_SIZE / 4.0 };
+            frame.stroke(
+                &Path::line(
+                    Point::new(sx, RULER_SIZE - length),
+                    Point::new(sx, RULER_SIZE),
+                ),
+                tick,
+            );
+            if major && sx > RULER_SIZE {
+                frame.fill_text(canvas::Text {
+                    content: x.to_string(),
+                    position: Point::new(sx + 3.0, 2.0),
+                    size: iced::Pixels(10.0),
+                    color: ink,
+                    ..canvas::Text::default()
+                });
+            }
+            x += minor;
+        }
+        let mut y = (top / minor as f32).floor() as i32 * minor;
+        while (y as f32) <= bottom {
+            let sy = view.to_screen(0.0, y as f32).y;
+            let major = y % step == 0;
+            let length = if major { RULER_SIZE } else { RULER_SIZE / 4.0 };
+            frame.stroke(
+                &Path::line(
+                    Point::new(RULER_SIZE - length, sy),
+                    Point::new(RULER_SIZE, sy),
+                ),
+                tick,
+            );
+            if major && sy > RULER_SIZE {
+                frame.fill_text(canvas::Text {
+                    content: y.to_string(),
+                    position: Point::new(2.0, sy + 3.0),
+                    size: iced::Pixels(10.0),
+                    color: ink,
+                    ..canvas::Text::default()
+                });
+            }
+            y += minor;
+        }
+    }
+    frame.fill_rectangle(Point::ORIGIN, Size::new(RULER_SIZE, RULER_SIZE), background);
+
+    let Some(p) = cursor else {
+        return;
+    };
+    let marker = canvas::Stroke::default()
+        .with_color(Color::from_rgb8(90, 170, 230))
+        .with_width(1.0);
+    frame.stroke(
+        &Path::line(Point::new(p.x, 0.0), Point::new(p.x, RULER_SIZE)),
+        marker,
+    );
+    frame.stroke(
+        &Path::line(Point::new(0.0, p.y), Point::new(RULER_SIZE, p.y)),
+        marker,
+    );
+
+    let (lx, ly) = view.to_logical(p);
+    frame.fill_text(canvas::Text {
+        content: format!("{}, {} {}", lx.round() as i32, ly.round() as i32, unit),
+        position: Point::new(RULER_SIZE + 6.0, size.height - 20.0),
+        size: iced::Pixels(13.0),
+        color: Color::from_rgb8(200, 200, 200),
+        ..canvas::Text::default()
+    });
 }
 
 struct LayoutCanvas<'a> {
     outputs: Vec<Output>,
     selected_idx: Option<usize>,
+    selection: Vec<usize>,
+    flagged: Vec<usize>,
+    origin: Option<usize>,
+    snap: SnapConfig,
+    show_rulers: bool,
+    show_grid: bool,
+    /// Millimetres per logical pixel when drawing panels at their real size
+    physical: Option<f32>,
+    /// Bezel width in millimetres for each output, drawn in the physical view
+    bezels: Vec<f32>,
+    fit_generation: u64,
     cache: &'a Cache,
 }
 
 impl<'a> LayoutCanvas<'a> {
-    fn logical_size(out: &Output, cm: &OutputMode) -> (i32, i32) {
-        let w = (cm.width as f32 / out.scale) as i32;
-        let h = (cm.height as f32 / out.scale) as i32;
-        match out.transform.as_str() {
-            "90" | "270" | "flipped-90" | "flipped-270" => (h, w),
-            _ => (w, h),
+    /// Frames the bounding box of every output with a margin around it. A
+    /// canvas with no room left above the tray gets an unscaled view instead.
+    fn fit_view(&self, bounds: Rectangle) -> View {
+        let bounds = match self.tray(bounds) {
+            Some(tray) => Rectangle {
+                height: tray.y,
+                ..bounds
+            },
+            None => bounds,
+        };
+        if bounds.width <= 0.0 || bounds.height <= 0.0 {
+            return View {
+                scale: 1.0,
+                offset_x: 0.0,
+                offset_y: 0.0,
+            };
+        }
+        let rects: Vec<_> = self
+            .outputs
+            .iter()
+            .filter(|o| o.enabled)
+            .map(|o| self.world_rect(o))
+            .collect();
+        let min_x = rects.iter().map(|r| r.x).reduce(f32::min).unwrap_or(0.0);
+        let min_y = rects.iter().map(|r| r.y).reduce(f32::min).unwrap_or(0.0);
+        let max_x = rects
+            .iter()
+            .map(|r| r.x + r.width)
+            .reduce(f32::max)
+            .unwrap_or(layout::FALLBACK_SIZE.0 as f32);
+        let max_y = rects
+            .iter()
+            .map(|r| r.y + r.height)
+            .reduce(f32::max)
+            .unwrap_or(layout::FALLBACK_SIZE.1 as f32);
+
+        let span_x = ((max_x - min_x) * FIT_MARGIN).max(1.0);
+        let span_y = ((max_y - min_y) * FIT_MARGIN).max(1.0);
+        let scale = (bounds.width / span_x)
+            .min(bounds.height / span_y)
+            .clamp(MIN_ZOOM, MAX_ZOOM);
+
+        let centre_x = (min_x + max_x) / 2.0;
+        let centre_y = (min_y + max_y) / 2.0;
+        View {
+            scale,
+            offset_x: bounds.width / 2.0 - centre_x * scale,
+            offset_y: bounds.height / 2.0 - centre_y * scale,
         }
     }
 
-    fn calculate_layout(&self, bounds: Rectangle) -> (f32, f32, f32, i32, i32) {
-        let mut total_w = 0;
-        let mut max_h = 1080;
+    /// The view the user has zoomed and panned to, or a fresh fit if they
+    /// haven't touched it yet or asked for a refit since.
+    fn view(&self, state: &CanvasState, bounds: Rectangle) -> View {
+        match state.view {
+            Some(view) if state.fit_generation == self.fit_generation => view,
+            _ => self.fit_view(bounds),
+        }
+    }
 
-        for out in &self.outputs {
-            let cm = out
-                .modes
-                .iter()
-                .find(|m| m.current)
-                .cloned()
-                .unwrap_or(OutputMode {
-                    width: 800,
-                    height: 600,
-                    refresh_rate: 60.0,
-                    current: true,
-                    preferred: false,
-                });
-            let (w_logical, h_logical) = Self::logical_size(out, &cm);
-            total_w += w_logical;
-            if h_logical > max_h {
-                max_h = h_logical;
+    fn key_action(&self, key: &keyboard::Key, modifiers: keyboard::Modifiers) -> Option<Message> {
+        use keyboard::key::Named;
+
+        let count = self.outputs.len();
+        if let keyboard::Key::Named(Named::Tab) = key {
+            if count == 0 {
+                return None;
             }
+            let next = match self.selected_idx {
+                Some(i) if modifiers.shift() => (i + count - 1) % count,
+                Some(i) => (i + 1) % count,
+                None => 0,
+            };
+            return Some(Message::MonitorClicked(self.outputs[next].id()));
         }
 
-        let span_x = (total_w as f32 * 1.5).max(4000.0);
-        let span_y = (max_h as f32 * 2.5).max(3000.0);
-        let scale = (bounds.width / span_x).min(bounds.height / span_y);
+        let idx = self.selected_idx?;
+        let id = self.outputs[idx].id();
+        let (horizontal, sign) = match key {
+            keyboard::Key::Named(Named::ArrowLeft) => (true, -1),
+            keyboard::Key::Named(Named::ArrowRight) => (true, 1),
+            keyboard::Key::Named(Named::ArrowUp) => (false, -1),
+            keyboard::Key::Named(Named::ArrowDown) => (false, 1),
+            keyboard::Key::Named(Named::Delete) => {
+                let enabled = self.outputs.iter().filter(|o| o.enabled).count();
+                return (self.outputs[idx].enabled && enabled > 1)
+                    .then_some(Message::EnabledToggled(false));
+            }
+            keyboard::Key::Character(c) if c.as_str().eq_ignore_ascii_case("r") => {
+                return Some(Message::Rotate(id, if modifiers.shift() { -1 } else { 1 }));
+            }
+            _ => return None,
+        };
 
-        let first_w = self
-            .outputs
-            .first()
-            .map(|out| {
-                let cm = out
-                    .modes
-                    .iter()
-                    .find(|m| m.current)
-                    .cloned()
-                    .unwrap_or(OutputMode {
-                        width: 800,
-                        height: 600,
-                        refresh_rate: 60.0,
-                        current: true,
-                        preferred: false,
-                    });
-                Self::logical_size(out, &cm).0 as f32
-            })
-            .unwrap_or(1920.0);
+        let distance = if modifiers.command() {
+            let moving = layout::logical_rect(&self.outputs[idx]);
+            let others = self.snap_targets(|i| i == idx);
+            let start = if horizontal { moving.x } else { moving.y };
+            layout::next_edge(moving, &others, horizontal, sign)? - start
+        } else if modifiers.shift() {
+            10 * sign
+        } else {
+            sign
+        };
 
-        let offset_x = bounds.width / 2.0 - (first_w / 2.0) * scale;
-        let offset_y = bounds.height / 2.0 - (max_h as f32 / 2.0) * scale;
+        Some(if horizontal {
+            Message::Nudge(id, distance, 0)
+        } else {
+            Message::Nudge(id, 0, distance)
+        })
+    }
 
-        (scale, offset_x, offset_y, 0, 0)
+    /// The tray is there whenever something could be dropped on it or is in it.
+    fn tray(&self, bounds: Rectangle) -> Option<Rectangle> {
+        (self.outputs.len() > 1).then(|| {
+            Rectangle::new(
+                Point::new(0.0, bounds.height - TRAY_HEIGHT),
+                Size::new(bounds.width, TRAY_HEIGHT),
+            )
+        })
     }
 
-    fn transformed_geometry(
-        &self,
-        out: &Output,
-        scale: f32,
-        offset_x: f32,
-        offset_y: f32,
-        min_x: i32,
-        min_y: i32,
-    ) -> (f32, f32, f32, f32) {
-        let cm = out
-            .modes
+    /// Screen rectangles for the disabled outputs lined up in the tray.
+    fn chips(&self, tray: Rectangle) -> Vec<(usize, Rectangle)> {
+        self.outputs
             .iter()
-            .find(|m| m.current)
-            .cloned()
-            .unwrap_or(OutputMode {
-                width: 800,
-                height: 600,
-                refresh_rate: 60.0,
-                current: true,
-                preferred: false,
+            .enumerate()
+            .filter(|(_, o)| !o.enabled)
+            .enumerate()
+            .map(|(n, (i, _))| {
+                let x = tray.x + CHIP_SPACING + n as f32 * (CHIP_WIDTH + CHIP_SPACING);
+                let chip = Rectangle::new(
+                    Point::new(x, tray.y + CHIP_SPACING),
+                    Size::new(CHIP_WIDTH, tray.height - 2.0 * CHIP_SPACING),
+                );
+                (i, chip)
+            })
+            .collect()
+    }
+
+    /// The enabled output under `p`, topmost first.
+    fn hit(&self, p: Point, view: View) -> Option<usize> {
+        self.outputs.iter().enumerate().rev().find_map(|(i, out)| {
+            let (x, y, w, h) = self.transformed_geometry(out, view);
+            (out.enabled && Rectangle::new(Point::new(x, y), Size::new(w, h)).contains(p))
+                .then_some(i)
+        })
+    }
+
+    /// Logical rectangles of every enabled output not in `skip`.
+    fn snap_targets(&self, skip: impl Fn(usize) -> bool) -> Vec<layout::Rect> {
+        (0..self.outputs.len())
+            .filter(|i| self.outputs[*i].enabled && !skip(*i))
+            .map(|i| layout::logical_rect(&self.outputs[i]))
+            .collect()
+    }
+
+    fn index_of(&self, id: &OutputId) -> Option<usize> {
+        self.outputs.iter().position(|o| o.id() == *id)
+    }
+
+    /// The menu for an output, empty if it has gone away.
+    fn menu_items(&self, id: &OutputId) -> Vec<(String, Message)> {
+        let Some(idx) = self.index_of(id) else {
+            return Vec::new();
+        };
+        let out = &self.outputs[idx];
+        let mut items = vec![
+            ("Rotate Left".to_string(), Message::Rotate(id.clone(), -1)),
+            ("Rotate Right".to_string(), Message::Rotate(id.clone(), 1)),
+            ("Flip".to_string(), Message::Flip(id.clone())),
+        ];
+        if self.origin != Some(idx) {
+            items.push(("Set as Origin".to_string(), Message::OriginSet(id.clone())));
+        }
+        if self.outputs.iter().filter(|o| o.enabled).count() > 1 {
+            let (x, y) = out.position;
+            items.push((
+                "Disable".to_string(),
+                Message::Park(vec![(id.clone(), x, y)]),
+            ));
+        }
+        for (j, other) in self.outputs.iter().enumerate() {
+            if j != idx && other.enabled {
+                items.push((
+                    format!("Mirror to {}", other.name),
+                    Message::Mirror(id.clone(), other.id()),
+                ));
+            }
+        }
+        if out.modes.iter().any(|m| m.preferred && !m.current) {
+            items.push((
+                "Reset to Preferred Mode".to_string(),
+                Message::UsePreferredMode(id.clone()),
+            ));
+        }
+        items.push((
+            "Copy monitorrule".to_string(),
+            Message::CopyMonitorRule(id.clone()),
+        ));
+        items
+    }
+
+    /// Where the menu is drawn, nudged back inside the canvas if it would
+    /// spill over an edge.
+    fn menu_rect(&self, menu: &Menu, bounds: Rectangle) -> Rectangle {
+        let height = self.menu_items(&menu.id).len() as f32 * MENU_ITEM_HEIGHT;
+        Rectangle::new(
+            Point::new(
+                menu.at.x.min(bounds.width - MENU_WIDTH).max(0.0),
+                menu.at.y.min(bounds.height - height).max(0.0),
+            ),
+            Size::new(MENU_WIDTH, height),
+        )
+    }
+
+    fn menu_item_at(&self, menu: &Menu, bounds: Rectangle, p: Point) -> Option<usize> {
+        let rect = self.menu_rect(menu, bounds);
+        rect.contains(p)
+            .then(|| ((p.y - rect.y) / MENU_ITEM_HEIGHT) as usize)
+    }
+
+    fn draw_menu(&self, frame: &mut canvas::Frame, menu: &Menu, bounds: Rectangle) {
+        let rect = self.menu_rect(menu, bounds);
+        frame.fill_rectangle(rect.position(), rect.size(), Color::from_rgb8(35, 35, 35));
+        for (n, (label, _)) in self.menu_items(&menu.id).into_iter().enumerate() {
+            let y = rect.y + n as f32 * MENU_ITEM_HEIGHT;
+            if menu.hovered == Some(n) {
+                frame.fill_rectangle(
+                    Point::new(rect.x, y),
+                    Size::new(MENU_WIDTH, MENU_ITEM_HEIGHT),
+                    Color::from_rgb8(60, 90, 130),
+                );
+            }
+            frame.fill_text(canvas::Text {
+                content: label,
+                position: Point::new(rect.x + 10.0, y + 6.0),
+                size: iced::Pixels(13.0),
+                color: Color::from_rgb8(220, 220, 220),
+                ..canvas::Text::default()
             });
-        let (w_logical, h_logical) = Self::logical_size(out, &cm);
+        }
+        frame.stroke(
+            &Path::rectangle(rect.position(), rect.size()),
+            canvas::Stroke::default()
+                .with_color(Color::from_rgb8(80, 80, 80))
+                .with_width(1.0),
+        );
+    }
+
+    fn draw_tray(&self, frame: &mut canvas::Frame, tray: Rectangle, dropping: bool) {
+        frame.fill_rectangle(
+            tray.position(),
+            tray.size(),
+            if dropping {
+                Color::from_rgb8(40, 30, 30)
+            } else {
+                Color::from_rgb8(22, 22, 22)
+            },
+        );
+        frame.stroke(
+            &Path::line(tray.position(), Point::new(tray.width, tray.y)),
+            canvas::Stroke::default()
+                .with_color(Color::from_rgb8(60, 60, 60))
+                .with_width(1.0),
+        );
+
+        let chips = self.chips(tray);
+        let hint = if dropping {
+            "Release to disable"
+        } else if chips.is_empty() {
+            "Drag an output here to disable it"
+        } else {
+            ""
+        };
+        let hint_x = chips.last().map_or(tray.x, |(_, c)| c.x + c.width) + CHIP_SPACING * 2.0;
+        frame.fill_text(canvas::Text {
+            content: hint.to_string(),
+            position: Point::new(hint_x, tray.y + tray.height / 2.0 - 7.0),
+            size: iced::Pixels(13.0),
+            color: Color::from_rgb8(120, 120, 120),
+            ..canvas::Text::default()
+        });
+
+        for (i, chip) in chips {
+            let out = &self.outputs[i];
+            let colour = output_colour(i);
+            let selected = Some(i) == self.selected_idx;
+            frame.fill_rectangle(
+                chip.position(),
+                chip.size(),
+                mix(
+                    colour,
+                    Color::from_rgb8(22, 22, 22),
+                    if selected { 0.5 } else { 0.8 },
+                ),
+            );
+            frame.stroke(
+                &Path::rectangle(chip.position(), chip.size()),
+                canvas::Stroke {
+                    line_dash: canvas::LineDash {
+                        segments: &[6.0, 4.0],
+                        offset: 0,
+                    },
+                    ..canvas::Stroke::default()
+                        .with_color(if selected { Color::WHITE } else { colour })
+                        .with_width(2.0)
+                },
+            );
+            let lines = [out.name.clone(), output_details(out).remove(0)];
+            for (n, line) in lines.into_iter().enumerate() {
+                frame.fill_text(canvas::Text {
+                    content: line,
+                    position: Point::new(chip.x + 8.0, chip.y + 8.0 + n as f32 * 18.0),
+                    size: iced::Pixels(if n == 0 { 15.0 } else { 11.0 }),
+                    color: Color::from_rgb8(210, 210, 210),
+                    ..canvas::Text::default()
+                });
+            }
+        }
+    }
+
+    /// Canvas units per logical pixel: 1 normally, millimetres in the
+    /// physical view.
+    fn world_scale(&self) -> f32 {
+        self.physical.unwrap_or(1.0)
+    }
+
+    /// Where an output sits in canvas units. The physical view draws each
+    /// panel at its real size, packed so neighbours in the layout stay
+    /// neighbours.
+    fn world_rect(&self, out: &Output) -> Rectangle {
+        let r = layout::logical_rect(out);
+        let Some(mm_per_px) = self.physical else {
+            return Rectangle::new(
+                Point::new(r.x as f32, r.y as f32),
+                Size::new(r.w as f32, r.h as f32),
+            );
+        };
+        let real_size = |o: &Output| {
+            layout::physical_extent(o).unwrap_or_else(|| {
+                let r = layout::logical_rect(o);
+                (r.w as f32 * mm_per_px, r.h as f32 * mm_per_px)
+            })
+        };
+        let (w, h) = real_size(out);
+        // Parked outputs aren't part of the layout, so only the enabled ones
+        // push each other around
+        let enabled: Vec<&Output> = self.outputs.iter().filter(|o| o.enabled).collect();
+        let position = enabled
+            .iter()
+            .position(|o| o.id() == out.id())
+            .map(|i| {
+                let rects: Vec<_> = enabled.iter().map(|o| layout::logical_rect(o)).collect();
+                let sizes: Vec<_> = enabled.iter().map(|o| real_size(o)).collect();
+                layout::physical_positions(&rects, &sizes, mm_per_px)[i]
+            })
+            .unwrap_or((r.x as f32 * mm_per_px, r.y as f32 * mm_per_px));
+        Rectangle::new(Point::new(position.0, position.1), Size::new(w, h))
+    }
 
-        let w = w_logical as f32 * scale;
-        let h = h_logical as f32 * scale;
-        let x = (out.position.0 - min_x) as f32 * scale + offset_x;
-        let y = (out.position.1 - min_y) as f32 * scale + offset_y;
+    /// Where a logical point lands outside the physical view. Panels there
+    /// are packed rather than scaled, so there is no single mapping.
+    fn logical_to_screen(&self, view: View, x: i32, y: i32) -> Point {
+        view.to_screen(x as f32, y as f32)
+    }
 
-        (x, y, w, h)
+    fn transformed_geometry(&self, out: &Output, view: View) -> (f32, f32, f32, f32) {
+        let r = self.world_rect(out);
+        let top_left = view.to_screen(r.x, r.y);
+        (
+            top_left.x,
+            top_left.y,
+            r.width * view.scale,
+            r.height * view.scale,
+        )
     }
 }
 
@@ -547,161 +2785,300 @@ impl<'a> Program<Message> for LayoutCanvas<'a> {
         bounds: Rectangle,
         cursor: mouse::Cursor,
     ) -> Option<Action<Message>> {
-        let (scale, offset_x, offset_y, min_x, min_y) = self.calculate_layout(bounds);
+        // Pin the view down on the first event so it doesn't refit itself
+        // while an output is being dragged
+        if state.view.is_none() || state.fit_generation != self.fit_generation {
+            state.view = Some(self.fit_view(bounds));
+            state.fit_generation = self.fit_generation;
+        }
+        let view = self.view(state, bounds);
+        // Screen pixels per logical pixel, for turning drags into moves
+        let scale = view.scale * self.world_scale();
 
         match event {
-            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left)) => {
-                if let Some(cursor_position) = cursor.position_in(bounds) {
-                    for (i, out) in self.outputs.iter().enumerate() {
-                        let (x, y, w, h) =
-                            self.transformed_geometry(out, scale, offset_x, offset_y, min_x, min_y);
-
-                        let rect = Rectangle::new(Point::new(x, y), Size::new(w, h));
-                        if rect.contains(cursor_position) {
-                            state.dragging = Some((i, cursor_position, out.position));
-                        }
+            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Right)) => {
+                let p = cursor.position_in(bounds)?;
+                match self.hit(p, view) {
+                    Some(i) => {
+                        let id = self.outputs[i].id();
+                        state.menu = Some(Menu {
+                            id: id.clone(),
+                            at: p,
+                            hovered: None,
+                        });
+                        return Some(Action::publish(Message::MonitorClicked(id)).and_capture());
                     }
-                    if let Some((i, _, _)) = state.dragging {
-                        return Some(Action::publish(Message::MonitorClicked(i)).and_capture());
+                    None if state.menu.take().is_some() => {
+                        return Some(Action::request_redraw().and_capture());
                     }
-                    state.dragging = None;
+                    None => {}
                 }
             }
-            Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Left)) => {
-                state.dragging = None;
+            Event::Mouse(mouse::Event::CursorMoved { .. }) if state.menu.is_some() => {
+                let p = cursor.position_in(bounds)?;
+                let menu = state.menu.as_ref()?;
+                let hovered = self.menu_item_at(menu, bounds, p);
+                if hovered != menu.hovered {
+                    state.menu = Some(Menu {
+                        hovered,
+                        ..state.menu.take()?
+                    });
+                    return Some(Action::request_redraw());
+                }
             }
-            Event::Mouse(mouse::Event::CursorMoved { position }) => {
-                if let Some((idx, start_cursor, start_logical)) = state.dragging {
-                    let delta_x = (position.x - start_cursor.x) / scale;
-                    let delta_y = (position.y - start_cursor.y) / scale;
-
-                    let mut new_x = start_logical.0 + delta_x.round() as i32;
-                    let mut new_y = start_logical.1 + delta_y.round() as i32;
-                    if new_x < 0 {
-                        new_x = 0;
-                    }
-                    if new_y < 0 {
-                        new_y = 0;
+            // Any click while the menu is open either picks an item or
+            // dismisses it, and goes no further
+            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left))
+                if state.menu.is_some() =>
+            {
+                let menu = state.menu.take()?;
+                let picked = cursor
+                    .position_in(bounds)
+                    .and_then(|p| self.menu_item_at(&menu, bounds, p))
+                    .and_then(|n| self.menu_items(&menu.id).into_iter().nth(n));
+                return Some(match picked {
+                    Some((_, message)) => Action::publish(message).and_capture(),
+                    None => Action::request_redraw().and_capture(),
+                });
+            }
+            Event::Keyboard(keyboard::Event::KeyPressed {
+                key: keyboard::Key::Named(keyboard::key::Named::Escape),
+                ..
+            }) if state.menu.is_some() => {
+                state.menu = None;
+                return Some(Action::request_redraw().and_capture());
+            }
+            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left)) => {
+                state.focused = cursor.position_in(bounds).is_some();
+                if let Some(cursor_position) = cursor.position_in(bounds) {
+                    if let Some(tray) = self.tray(bounds)
+                        && tray.contains(cursor_position)
+                    {
+                        let (i, _) = self
+                            .chips(tray)
+                            .into_iter()
+                            .find(|(_, chip)| chip.contains(cursor_position))?;
+                        let id = self.outputs[i].id();
+                        state.unparking = Some((id.clone(), cursor_position));
+                        return Some(Action::publish(Message::MonitorClicked(id)).and_capture());
                     }
-
-                    let snap_threshold = 40;
-
-                    let out = &self.outputs[idx];
-                    let cm = out
-                        .modes
-                        .iter()
-                        .find(|m| m.current)
-                        .cloned()
-                        .unwrap_or(OutputMode {
-                            width: 800,
-                            height: 600,
-                            refresh_rate: 60.0,
-                            current: true,
-                            preferred: false,
-                        });
-                    let (w, h) = Self::logical_size(out, &cm);
-
-                    let mut snapped_x = new_x;
-                    let mut snapped_y = new_y;
-                    let mut min_dist_x = snap_threshold;
-                    let mut min_dist_y = snap_threshold;
-
-                    let my_left = new_x;
-                    let my_right = new_x + w;
-                    let my_top = new_y;
-                    let my_bottom = new_y + h;
-
-                    for (i, other) in self.outputs.iter().enumerate() {
-                        if i == idx {
-                            continue;
-                        }
-                        let other_cm =
-                            other
-                                .modes
-                                .iter()
-                                .find(|m| m.current)
-                                .cloned()
-                                .unwrap_or(OutputMode {
-                                    width: 800,
-                                    height: 600,
-                                    refresh_rate: 60.0,
-                                    current: true,
-                                    preferred: false,
-                                });
-                        let (other_w, other_h) = Self::logical_size(other, &other_cm);
-
-                        let other_left = other.position.0;
-                        let other_right = other.position.0 + other_w;
-                        let other_top = other.position.1;
-                        let other_bottom = other.position.1 + other_h;
-
-                        let x_overlap = my_left < other_right + snap_threshold
-                            && my_right > other_left - snap_threshold;
-                        let y_overlap = my_top < other_bottom + snap_threshold
-                            && my_bottom > other_top - snap_threshold;
-
-                        if y_overlap {
-                            if (my_left - other_right).abs() < min_dist_x {
-                                min_dist_x = (my_left - other_right).abs();
-                                snapped_x = other_right;
-                            }
-                            if (my_right - other_left).abs() < min_dist_x {
-                                min_dist_x = (my_right - other_left).abs();
-                                snapped_x = other_left - w;
-                            }
-                            if (my_left - other_left).abs() < min_dist_x {
-                                min_dist_x = (my_left - other_left).abs();
-                                snapped_x = other_left;
-                            }
+                    let hit = self.hit(cursor_position, view);
+                    let extend = state.modifiers.shift() || state.modifiers.command();
+                    return match hit {
+                        Some(i) if extend => Some(
+                            Action::publish(Message::MonitorToggled(self.outputs[i].id()))
+                                .and_capture(),
+                        ),
+                        Some(i) => {
+                            let group = if self.selection.contains(&i) {
+                                self.selection.clone()
+                            } else {
+                                vec![i]
+                            };
+                            let id = self.outputs[i].id();
+                            state.dragging = Some(Drag {
+                                anchor: id.clone(),
+                                start_cursor: cursor_position,
+                                members: group
+                                    .into_iter()
+                                    .map(|j| (self.outputs[j].id(), self.outputs[j].position))
+                                    .collect(),
+                            });
+                            Some(Action::publish(Message::MonitorClicked(id)).and_capture())
                         }
-
-                        if x_overlap {
-                            if (my_top - other_bottom).abs() < min_dist_y {
-                                min_dist_y = (my_top - other_bottom).abs();
-                                snapped_y = other_bottom;
-                            }
-                            if (my_bottom - other_top).abs() < min_dist_y {
-                                min_dist_y = (my_bottom - other_top).abs();
-                                snapped_y = other_top - h;
-                            }
-                            if (my_top - other_top).abs() < min_dist_y {
-                                min_dist_y = (my_top - other_top).abs();
-                                snapped_y = other_top;
-                            }
+                        None => {
+                            state.band = Some((cursor_position, cursor_position));
+                            Some(Action::capture())
                         }
-                    }
-
-                    if snapped_x == new_x {
-                        snapped_x = (snapped_x as f32 / 10.0).round() as i32 * 10;
-                    }
-                    if snapped_y == new_y {
-                        snapped_y = (snapped_y as f32 / 10.0).round() as i32 * 10;
-                    }
-
-                    if snapped_x < 0 {
-                        snapped_x = 0;
-                    }
-                    if snapped_y < 0 {
-                        snapped_y = 0;
-                    }
+                    };
+                }
+            }
+            Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Left))
+                if state.band.is_some() =>
+            {
+                let (start, end) = state.band.take()?;
+                let band = Rectangle::new(
+                    Point::new(start.x.min(end.x), start.y.min(end.y)),
+                    Size::new((end.x - start.x).abs(), (end.y - start.y).abs()),
+                );
+                // A plain click on empty space shouldn't wipe the selection
+                if band.width < BAND_MIN_SIZE && band.height < BAND_MIN_SIZE {
+                    return Some(Action::request_redraw());
+                }
+                let hits = self
+                    .outputs
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, out)| {
+                        let (x, y, w, h) = self.transformed_geometry(out, view);
+                        out.enabled
+                            && band.intersects(&Rectangle::new(Point::new(x, y), Size::new(w, h)))
+                    })
+                    .map(|(_, out)| out.id())
+                    .collect();
+                let additive = state.modifiers.shift() || state.modifiers.command();
+                return Some(Action::publish(Message::SelectionSet(hits, additive)));
+            }
+            Event::Mouse(mouse::Event::CursorMoved { .. }) if state.band.is_some() => {
+                if let (Some((start, _)), Some(p)) = (state.band, cursor.position_in(bounds)) {
+                    state.band = Some((start, p));
+                    return Some(Action::request_redraw());
+                }
+            }
+            Event::Keyboard(keyboard::Event::KeyPressed {
+                key: keyboard::Key::Character(c),
+                modifiers,
+                ..
+            }) if state.focused && c.as_str() == "f" && modifiers.is_empty() => {
+                state.view = Some(self.fit_view(bounds));
+                self.cache.clear();
+                return Some(Action::capture());
+            }
+            Event::Keyboard(keyboard::Event::KeyPressed { key, modifiers, .. })
+                if state.focused =>
+            {
+                return self
+                    .key_action(key, *modifiers)
+                    .map(|message| Action::publish(message).and_capture());
+            }
+            Event::Mouse(mouse::Event::CursorMoved { .. }) if state.unparking.is_some() => {
+                if let (Some((id, _)), Some(p)) =
+                    (state.unparking.take(), cursor.position_in(bounds))
+                {
+                    state.unparking = Some((id, p));
+                    return Some(Action::request_redraw());
+                }
+            }
+            Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Left))
+                if state.unparking.is_some() =>
+            {
+                let (id, p) = state.unparking.take()?;
+                let i = self.index_of(&id)?;
+                let in_tray = self.tray(bounds).is_some_and(|tray| tray.contains(p));
+                if in_tray || cursor.position_in(bounds).is_none() {
+                    return Some(Action::request_redraw());
+                }
+                // Drop it centred on the cursor, snapped like any other move
+                let (lx, ly) = view.to_logical(p);
+                let ws = self.world_scale();
+                let r = layout::logical_rect(&self.outputs[i]);
+                let moving = layout::Rect {
+                    x: (lx / ws) as i32 - r.w / 2,
+                    y: (ly / ws) as i32 - r.h / 2,
+                    ..r
+                };
+                let (x, y) = if state.modifiers.alt() {
+                    (moving.x, moving.y)
+                } else {
+                    let snapped = layout::snap(moving, &self.snap_targets(|j| j == i), &self.snap);
+                    (snapped.x, snapped.y)
+                };
+                return Some(Action::publish(Message::Unpark(id, x, y)));
+            }
+            Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Left))
+                if state.dragging.is_some() =>
+            {
+                let drag = state.dragging.take()?;
+                state.guides.clear();
+                let over_tray = self
+                    .tray(bounds)
+                    .zip(cursor.position_in(bounds))
+                    .is_some_and(|(tray, p)| tray.contains(p));
+                if over_tray {
+                    let moves = drag
+                        .members
+                        .into_iter()
+                        .map(|(j, (x, y))| (j, x, y))
+                        .collect();
+                    return Some(Action::publish(Message::Park(moves)));
+                }
+                return Some(Action::publish(Message::DragFinished));
+            }
+            Event::Keyboard(keyboard::Event::ModifiersChanged(modifiers)) => {
+                state.modifiers = *modifiers;
+            }
+            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Middle)) => {
+                if let Some(p) = cursor.position_in(bounds) {
+                    state.panning = Some((p, view));
+                    return Some(Action::capture());
+                }
+            }
+            Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Middle))
+                if state.panning.is_some() =>
+            {
+                state.panning = None;
+            }
+            Event::Mouse(mouse::Event::WheelScrolled { delta }) => {
+                if let Some(p) = cursor.position_in(bounds) {
+                    let lines = match delta {
+                        mouse::ScrollDelta::Lines { y, .. } => *y,
+                        mouse::ScrollDelta::Pixels { y, .. } => *y / 50.0,
+                    };
+                    state.view = Some(view.zoomed(1.1_f32.powf(lines), p));
+                    self.cache.clear();
+                    return Some(Action::capture());
+                }
+            }
+            Event::Mouse(mouse::Event::CursorMoved { .. }) if state.panning.is_some() => {
+                if let (Some((start, start_view)), Some(p)) =
+                    (state.panning, cursor.position_in(bounds))
+                {
+                    state.view = Some(View {
+                        offset_x: start_view.offset_x + p.x - start.x,
+                        offset_y: start_view.offset_y + p.y - start.y,
+                        ..start_view
+                    });
+                    self.cache.clear();
+                }
+            }
+            Event::Mouse(mouse::Event::CursorMoved { .. }) => {
+                let position = cursor.position_in(bounds)?;
+                if let Some(drag) = &state.dragging {
+                    let idx = self.index_of(&drag.anchor)?;
+                    let (_, start_logical) =
+                        *drag.members.iter().find(|(j, _)| *j == drag.anchor)?;
+                    let delta_x = (position.x - drag.start_cursor.x) / scale;
+                    let delta_y = (position.y - drag.start_cursor.y) / scale;
+
+                    let new_x = start_logical.0 + delta_x.round() as i32;
+                    let new_y = start_logical.1 + delta_y.round() as i32;
+
+                    let moving = layout::Rect {
+                        x: new_x,
+                        y: new_y,
+                        ..layout::logical_rect(&self.outputs[idx])
+                    };
+                    let (snapped_x, snapped_y) = if state.modifiers.alt() {
+                        state.guides.clear();
+                        (new_x, new_y)
+                    } else {
+                        // The rest of the group moves rigidly, so only
+                        // outputs outside it are snap targets
+                        let others = self.snap_targets(|i| {
+                            drag.members.iter().any(|(j, _)| self.outputs[i].id() == *j)
+                        });
+                        let snapped = layout::snap(moving, &others, &self.snap);
+                        state.guides = snapped.guides;
+                        (snapped.x, snapped.y)
+                    };
 
-                    return Some(Action::publish(Message::MonitorPositioned(
-                        idx, snapped_x, snapped_y,
-                    )));
+                    let (dx, dy) = (snapped_x - start_logical.0, snapped_y - start_logical.1);
+                    let moves = drag
+                        .members
+                        .iter()
+                        .map(|(j, (x, y))| (j.clone(), x + dx, y + dy))
+                        .collect();
+                    return Some(Action::publish(Message::MonitorsPositioned(moves)));
                 } else {
-                    let mut new_hovered = None;
-                    for (i, out) in self.outputs.iter().enumerate() {
-                        let (x, y, w, h) =
-                            self.transformed_geometry(out, scale, offset_x, offset_y, min_x, min_y);
-                        let rect = Rectangle::new(Point::new(x, y), Size::new(w, h));
-                        if rect.contains(*position) {
-                            new_hovered = Some(i);
-                        }
-                    }
+                    let new_hovered = self.hit(position, view);
                     if state.hovered != new_hovered {
                         state.hovered = new_hovered;
                         self.cache.clear();
                     }
+                    // The rulers track the cursor
+                    if self.show_rulers {
+                        return Some(Action::request_redraw());
+                    }
                 }
             }
             _ => {}
@@ -715,61 +3092,91 @@ impl<'a> Program<Message> for LayoutCanvas<'a> {
         renderer: &Renderer,
         _theme: &Theme,
         bounds: Rectangle,
-        _cursor: mouse::Cursor,
+        cursor: mouse::Cursor,
     ) -> Vec<Geometry> {
         let geometry = self.cache.draw(renderer, bounds.size(), |frame| {
             frame.fill_rectangle(Point::ORIGIN, bounds.size(), Color::from_rgb8(15, 15, 15));
 
-            let (scale, offset_x, offset_y, min_x, min_y) = self.calculate_layout(bounds);
+            let view = self.view(state, bounds);
+            let scale = view.scale;
+
+            if self.show_grid {
+                draw_grid(frame, view, bounds.size());
+            }
 
             for (i, out) in self.outputs.iter().enumerate() {
-                let (x, y, w, h) =
-                    self.transformed_geometry(out, scale, offset_x, offset_y, min_x, min_y);
+                // Disabled outputs live in the tray instead
+                if !out.enabled {
+                    continue;
+                }
+                let (x, y, w, h) = self.transformed_geometry(out, view);
 
                 let rect = Rectangle::new(Point::new(x, y), Size::new(w, h));
 
-                let is_selected = Some(i) == self.selected_idx;
+                let is_primary = Some(i) == self.selected_idx;
+                let is_selected = is_primary || self.selection.contains(&i);
                 let is_hovered = Some(i) == state.hovered;
+                let is_flagged = self.flagged.contains(&i);
 
+                let colour = output_colour(i);
                 let fill_color = if is_selected {
-                    Color::from_rgb8(220, 220, 220)
+                    mix(colour, Color::WHITE, 0.6)
                 } else if is_hovered {
-                    Color::from_rgb8(60, 60, 60)
+                    mix(colour, Color::from_rgb8(15, 15, 15), 0.55)
                 } else {
-                    Color::from_rgb8(35, 35, 35)
+                    mix(colour, Color::from_rgb8(15, 15, 15), 0.75)
                 };
 
-                let stroke_color = if is_selected {
+                let stroke_color = if is_flagged {
+                    Color::from_rgb8(210, 70, 70)
+                } else if is_selected {
                     Color::from_rgb8(255, 255, 255)
-                } else if is_hovered {
-                    Color::from_rgb8(150, 150, 150)
                 } else {
-                    Color::from_rgb8(20, 20, 20)
+                    colour
                 };
 
+                let bezel = self.bezels.get(i).copied().unwrap_or(0.0) * view.scale;
+                if self.physical.is_some() && bezel > 0.0 {
+                    frame.fill_rectangle(
+                        Point::new(x - bezel, y - bezel),
+                        Size::new(w + 2.0 * bezel, h + 2.0 * bezel),
+                        Color::from_rgb8(45, 45, 45),
+                    );
+                }
+
                 frame.fill_rectangle(rect.position(), rect.size(), fill_color);
 
                 frame.stroke(
                     &Path::rectangle(rect.position(), rect.size()),
                     canvas::Stroke::default()
                         .with_color(stroke_color)
-                        .with_width(if is_selected { 3.0 } else { 2.0 }),
+                        .with_width(if is_primary { 3.0 } else { 2.0 }),
                 );
 
+                draw_top_edge(frame, rect, &out.transform, stroke_color);
+
+                if Some(i) == self.origin {
+                    frame.fill(
+                        &Path::circle(rect.position(), 6.0),
+                        Color::from_rgb8(230, 180, 60),
+                    );
+                }
+
                 let text_x = x + 16.0;
                 let mut text_y = y + 16.0;
-                let font_scale = scale.min(2.0).max(0.5);
-
-                let mut name_text = canvas::Text::default();
-                name_text.content = out.name.clone();
-                name_text.position = Point::new(text_x, text_y);
-                name_text.size = iced::Pixels(48.0 * font_scale);
-                name_text.color = if is_selected {
-                    Color::BLACK
-                } else {
-                    Color::from_rgb8(230, 230, 230)
-                };
-                frame.fill_text(name_text);
+                let font_scale = scale.clamp(0.5, 2.0);
+
+                frame.fill_text(canvas::Text {
+                    content: out.name.clone(),
+                    position: Point::new(text_x, text_y),
+                    size: iced::Pixels(48.0 * font_scale),
+                    color: if is_selected {
+                        Color::BLACK
+                    } else {
+                        Color::from_rgb8(230, 230, 230)
+                    },
+                    ..canvas::Text::default()
+                });
 
                 text_y += 50.0 * font_scale;
 
@@ -780,7 +3187,7 @@ impl<'a> Program<Message> for LayoutCanvas<'a> {
                 let mut lines = Vec::new();
                 let mut current_line = String::new();
 
-                for word in out.description.split_whitespace() {
+                for word in out.description.iter().flat_map(|d| d.split_whitespace()) {
                     if current_line.len() + word.len() + 1 > max_chars && !current_line.is_empty() {
                         lines.push(current_line);
                         current_line = word.to_string();
@@ -796,21 +3203,194 @@ impl<'a> Program<Message> for LayoutCanvas<'a> {
                 }
 
                 for line in lines {
-                    let mut desc_text = canvas::Text::default();
-                    desc_text.content = line;
-                    desc_text.position = Point::new(text_x, text_y);
-                    desc_text.size = iced::Pixels(text_size);
-                    desc_text.color = if is_selected {
-                        Color::from_rgb8(40, 40, 40)
-                    } else {
-                        Color::from_rgb8(160, 160, 160)
-                    };
-                    frame.fill_text(desc_text);
+                    frame.fill_text(canvas::Text {
+                        content: line,
+                        position: Point::new(text_x, text_y),
+                        size: iced::Pixels(text_size),
+                        color: if is_selected {
+                            Color::from_rgb8(40, 40, 40)
+                        } else {
+                            Color::from_rgb8(160, 160, 160)
+                        },
+                        ..canvas::Text::default()
+                    });
+                    text_y += text_size * 1.3;
+                }
+
+                text_y += text_size * 0.5;
+                for line in output_details(out) {
+                    frame.fill_text(canvas::Text {
+                        content: line,
+                        position: Point::new(text_x, text_y),
+                        size: iced::Pixels(text_size),
+                        color: if is_selected {
+                            Color::from_rgb8(20, 20, 20)
+                        } else {
+                            Color::from_rgb8(200, 200, 200)
+                        },
+                        ..canvas::Text::default()
+                    });
                     text_y += text_size * 1.3;
                 }
             }
         });
 
-        vec![geometry]
+        let view = self.view(state, bounds);
+        let mut overlay = canvas::Frame::new(renderer, bounds.size());
+        let guide_stroke = canvas::Stroke::default()
+            .with_color(Color::from_rgb8(90, 170, 230))
+            .with_width(1.0);
+        // Guides and distances are in logical pixels, which don't line up
+        // with the packed panels of the physical view
+        let logical = self.physical.is_none();
+        for guide in state.guides.iter().filter(|_| logical) {
+            let (from, to) = match *guide {
+                Guide::Vertical(x) => {
+                    let sx = self.logical_to_screen(view, x, 0).x;
+                    (Point::new(sx, 0.0), Point::new(sx, bounds.height))
+                }
+                Guide::Horizontal(y) => {
+                    let sy = self.logical_to_screen(view, 0, y).y;
+                    (Point::new(0.0, sy), Point::new(bounds.width, sy))
+                }
+            };
+            overlay.stroke(&Path::line(from, to), guide_stroke);
+        }
+
+        if let Some((start, end)) = state.band {
+            let position = Point::new(start.x.min(end.x), start.y.min(end.y));
+            let size = Size::new((end.x - start.x).abs(), (end.y - start.y).abs());
+            overlay.fill_rectangle(position, size, Color::from_rgba8(90, 170, 230, 0.15));
+            overlay.stroke(&Path::rectangle(position, size), guide_stroke);
+        }
+
+        if logical
+            && let Some(drag) = &state.dragging
+            && let Some(anchor) = self.index_of(&drag.anchor)
+        {
+            let others =
+                self.snap_targets(|i| drag.members.iter().any(|(j, _)| self.outputs[i].id() == *j));
+            let moving = layout::logical_rect(&self.outputs[anchor]);
+            let colour = Color::from_rgb8(230, 200, 90);
+            for m in layout::measure_neighbours(moving, &others) {
+                let from = self.logical_to_screen(view, m.from.0, m.from.1);
+                let to = self.logical_to_screen(view, m.to.0, m.to.1);
+                overlay.stroke(
+                    &Path::line(from, to),
+                    canvas::Stroke::default().with_color(colour).with_width(1.0),
+                );
+                overlay.fill_text(canvas::Text {
+                    content: format!("{} px", m.distance),
+                    position: Point::new((from.x + to.x) / 2.0 + 4.0, (from.y + to.y) / 2.0 + 4.0),
+                    size: iced::Pixels(13.0),
+                    color: colour,
+                    ..canvas::Text::default()
+                });
+            }
+        }
+
+        if let Some(tray) = self.tray(bounds) {
+            let dropping = state.dragging.is_some()
+                && cursor.position_in(bounds).is_some_and(|p| tray.contains(p));
+            self.draw_tray(&mut overlay, tray, dropping);
+        }
+
+        if let Some((i, p)) = state
+            .unparking
+            .as_ref()
+            .and_then(|(id, p)| Some((self.index_of(id)?, *p)))
+        {
+            let r = self.world_rect(&self.outputs[i]);
+            let size = Size::new(r.width * view.scale, r.height * view.scale);
+            let position = Point::new(p.x - size.width / 2.0, p.y - size.height / 2.0);
+            let colour = output_colour(i);
+            overlay.fill_rectangle(position, size, colour.scale_alpha(0.3));
+            overlay.stroke(
+                &Path::rectangle(position, size),
+                canvas::Stroke::default().with_color(colour).with_width(2.0),
+            );
+        }
+
+        if self.show_rulers {
+            draw_rulers(
+                &mut overlay,
+                view,
+                bounds.size(),
+                cursor.position_in(bounds),
+                if self.physical.is_some() { "mm" } else { "px" },
+            );
+        }
+
+        if let Some(menu) = &state.menu {
+            self.draw_menu(&mut overlay, menu, bounds);
+        }
+
+        vec![geometry, overlay.into_geometry()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::parse_wlr_randr_output;
+    use crate::settings::AppSettings;
+
+    fn docked() -> MangoDisplay {
+        let outputs = parse_wlr_randr_output(include_str!("../tests/fixtures/laptop_docked.txt"));
+        MangoDisplay::new(outputs, AppSettings::default(), BTreeMap::new())
+    }
+
+    fn positions(app: &MangoDisplay) -> Vec<(i32, i32)> {
+        app.outputs
+            .iter()
+            .filter(|o| o.enabled)
+            .map(|o| o.position)
+            .collect()
+    }
+
+    #[test]
+    fn test_normalize_positions() {
+        // Without an origin only negative space is taken out of the layout
+        let mut app = docked();
+        app.normalize_positions();
+        assert_eq!(positions(&app), [(0, 440), (1600, 0)]);
+        app.normalize_positions();
+        assert_eq!(app.history.undo_labels().count(), 1);
+        assert!(app.undo());
+        assert_eq!(positions(&app), [(0, 0), (1600, -440)]);
+
+        // An origin is pinned to 0,0 even if that pushes others negative
+        let mut app = docked();
+        app.origin = Some("DP-3".to_string());
+        app.normalize_positions();
+        assert_eq!(positions(&app), [(-1600, 440), (0, 0)]);
+
+        // A disabled origin doesn't count
+        app.origin = Some("DP-4".to_string());
+        app.normalize_positions();
+        assert_eq!(positions(&app), [(0, 440), (1600, 0)]);
+    }
+
+    #[test]
+    fn test_targets() {
+        let mut app = docked();
+        assert_eq!(app.targets(), [0]);
+
+        let ids: Vec<OutputId> = app.outputs.iter().map(Output::id).collect();
+        app.selection = vec![ids[2].clone(), ids[1].clone()];
+        app.selected = Some(ids[1].clone());
+        assert_eq!(app.targets(), [2, 1]);
+        assert_eq!(app.targets_label("Rotate"), "Rotate 2 outputs");
+
+        // Outputs that have gone away are skipped
+        app.outputs.remove(2);
+        assert_eq!(app.targets(), [1]);
+        assert_eq!(app.targets_label("Rotate"), "Rotate DP-3");
+
+        // With nothing multi-selected, edits go to the output in the sidebar
+        app.selection.clear();
+        assert_eq!(app.targets(), [1]);
+        app.selected = None;
+        assert!(app.targets().is_empty());
     }
 }